@@ -1,5 +1,5 @@
 // use clap::{App, Arg, SubCommand, AppSettings};
-use clap::App;
+use clap::{App, Arg, SubCommand};
 
 pub struct Chore;
 
@@ -10,6 +10,10 @@ impl Chore {
              (author: crate_authors!())
              (@setting DeriveDisplayOrder)
              (@setting SubcommandRequiredElseHelp)
+             (@arg db: --db +takes_value +global "Path of the task database")
+             (@arg backend: --backend +takes_value +global
+                 possible_values(&["rocksdb", "fs", "memory"])
+                 "Storage backend of the task database")
              (@subcommand new =>
                  (about: "Create new task")
                  (@arg task: +required)
@@ -17,6 +21,7 @@ impl Chore {
                  (@arg filename: )
                  (@arg src_task: -t --task +takes_value)
                  (@arg editor: -e --editor +takes_value)
+                 (@arg interpreter: -I --interpreter +takes_value "Command line which runs the task, like 'bash -eu'")
              )
              (@subcommand edit =>
                  (about: "Edit a task")
@@ -24,13 +29,40 @@ impl Chore {
              )
              (@subcommand run =>
                  (about: "Run a task")
-                 (@arg task: +required)
-                 (@arg args: +multiple)
+                 (usage: "chore run [FLAGS] [OPTIONS] <task> [args]...\n    chore run [FLAGS] [OPTIONS] --parallel <task>...")
+                 (@setting TrailingVarArg)
+                 (@arg task: +required +multiple +allow_hyphen_values
+                     "The task, then the arguments passed to it")
+                 (@arg env: -e --env +takes_value +multiple number_of_values(1)
+                     "Set a variable for the task, like KEY=VALUE")
+                 (@arg dotenv: --dotenv "Load .env files from the current directory up to the root")
+                 (@arg print_env: --("print-env") "Print the environment of the task instead of running it")
+                 (@arg keep_going: -k --("keep-going") "Run the other tasks when a dependency fails")
+                 (@arg parallel: -p --parallel "Run all the given tasks at once, with their output prefixed")
+                 (@arg jobs: -j --jobs +takes_value {is_jobs} "How many tasks run at the same time with --parallel")
+                 (@arg log: -l --log "Write the output to a log as well, see 'chore logs'")
+                 (@arg timeout: --timeout +takes_value {is_timeout}
+                     "Stop the tasks which run longer, like 30s or 10m. 0 for no limit")
+                 (@arg watch: -w --watch +takes_value +multiple min_values(0) require_equals(true)
+                     use_delimiter(true)
+                     "Run again when files change, like --watch='src/**/*.rs,*.toml' (default: the watch option)")
+                 (@arg on_change: --("on-change") +takes_value possible_values(&["restart", "queue"])
+                     "Whether a change stops the run or waits for it with --watch (default: restart)")
              )
              (@subcommand show =>
                  (about: "Print the details of the task")
                  (@arg task: +required)
              )
+             (@subcommand set =>
+                 (about: "Change options of a task: inherit, extension, interpreter, description, tags")
+                 (@arg task: +required)
+                 (@arg options: +required +multiple "key=value pairs")
+             )
+             (@subcommand unset =>
+                 (about: "Put options of a task back to the default")
+                 (@arg task: +required)
+                 (@arg keys: +required +multiple "Names of the options")
+             )
              (@subcommand rename =>
                  (about: "Rename a task")
                  (@arg task: +required)
@@ -40,9 +72,153 @@ impl Chore {
                  (about: "Remove a task")
                  (@arg task: +required)
              )
+             (@subcommand alias =>
+                 (about: "Give a task another name, or print the aliases")
+                 (@arg name: "The alias (default: print the aliases of current directory)")
+                 (@arg task: "The task it stands for")
+                 (@arg inherit: -i --inherit "Make it available in subdirectories")
+                 (@arg delete: -d --delete requires[name] conflicts_with[task inherit] "Remove the alias")
+             )
              (@subcommand ls =>
                  (about: "Print tasks belong to current directory")
+                 (@arg long: -l --long "Print tags, timestamps and descriptions as well")
+                 (@arg tag: -t --tag +takes_value "Print only the tasks with the tag")
+             )
+             (@subcommand history =>
+                 (about: "Print when tasks were run and how they ended")
+                 (@arg task: "Print only the runs of the task")
+                 (@arg failed: --failed "Print only the runs which failed")
+                 (@arg since: --since +takes_value {is_since} "Print only the runs since, like 2h, 7d or 2018-05-01")
+                 (@arg count: -n +takes_value {is_count} "How many runs to print, 0 for all (default: 20)")
+                 (@arg output: --output "Print the end of the output kept for the runs")
+             )
+             (@subcommand logs =>
+                 (about: "Print the log of a run with --log")
+                 (@arg task: +required)
+                 (@arg run: --run +takes_value {is_run} "Which run to print (default: the last one)")
+                 (@arg follow: -f --follow "Keep printing the log while the task runs")
+             )
+             (@subcommand doctor =>
+                 (about: "Find tasks whose directory is gone and broken tasks")
+                 (@arg root: --root +takes_value "Where to look for relocated directories (default: home)")
+                 (@arg check: --check "Only report problems without fixing them")
+             )
+             (@subcommand export =>
+                 (about: "Write the tasks of current directory to a bundle")
+                 (@arg recursive: -r --recursive "Include the tasks of subdirectories")
+                 (@arg output: -o --output +takes_value "Bundle file to write (default: stdout)")
+             )
+             (@subcommand import =>
+                 (about: "Create tasks from a bundle")
+                 (@arg file: +required)
+                 (@arg into: --into +takes_value "Directory to import into (default: current directory)")
+                 (@arg conflict: --conflict +takes_value
+                     possible_values(&["skip", "overwrite", "prompt"])
+                     "What to do when a task already exists (default: prompt)")
+             )
+             (@subcommand log =>
+                 (about: "Print the revisions of a task")
+                 (@arg task: +required)
+             )
+             (@subcommand diff =>
+                 (about: "Compare revisions of a task")
+                 (@arg task: +required)
+                 (@arg from: {is_revision} "Old revision (default: the previous one)")
+                 (@arg to: {is_revision} "New revision (default: the current task)")
+             )
+             (@subcommand revert =>
+                 (about: "Restore a task to one of its revisions")
+                 (@arg task: +required)
+                 (@arg revision: +required {is_revision})
+             )
+             (@subcommand daemon =>
+                 (about: "Run the tasks on their schedules until it is stopped")
+             )
+             (@subcommand schedule =>
+                 (about: "Manage the schedules of tasks")
+                 (@setting SubcommandRequiredElseHelp)
+                 (@subcommand ls =>
+                     (about: "Print when the scheduled tasks run next")
+                 )
              )
+             (@subcommand db =>
+                 (about: "Manage the task database")
+                 (@setting SubcommandRequiredElseHelp)
+                 (@subcommand migrate =>
+                     (about: "Upgrade the database to the current schema")
+                     (@arg dry_run: --("dry-run") "Report what would change without writing")
+                 )
+             )
+        )
+        .subcommand(dir_command(
+            "mv-dir",
+            "Move all tasks of a directory to another directory",
+        ))
+        .subcommand(dir_command(
+            "cp-dir",
+            "Copy all tasks of a directory to another directory",
+        ))
+    }
+}
+
+// clap_app! doesn't accept a hyphenated subcommand name, so these are built by hand.
+fn dir_command(name: &'static str, about: &'static str) -> App<'static, 'static> {
+    SubCommand::with_name(name)
+        .about(about)
+        .arg(Arg::with_name("from").required(true))
+        .arg(Arg::with_name("to").required(true))
+        .arg(
+            Arg::with_name("recursive")
+                .short("r")
+                .long("recursive")
+                .help("Include the tasks of subdirectories"),
         )
+        .arg(
+            Arg::with_name("conflict")
+                .long("conflict")
+                .takes_value(true)
+                .possible_values(&["skip", "overwrite", "prompt"])
+                .help("What to do when a task already exists (default: prompt)"),
+        )
+}
+
+fn is_jobs(v: String) -> Result<(), String> {
+    parse_jobs(&v).map(|_| ())
+}
+
+pub fn parse_jobs(v: &str) -> Result<usize, String> {
+    match v.parse::<usize>() {
+        Ok(jobs) if jobs > 0 => Ok(jobs),
+        _ => Err(format!("'{}' is not a number of jobs", v)),
     }
 }
+
+fn is_since(v: String) -> Result<(), String> {
+    crate::time::parse_since(&v, 0)
+        .map(|_| ())
+        .ok_or_else(|| format!("'{}' is not like 2h, 7d or 2018-05-01", v))
+}
+
+fn is_count(v: String) -> Result<(), String> {
+    v.parse::<usize>()
+        .map(|_| ())
+        .map_err(|_| format!("'{}' is not a number", v))
+}
+
+fn is_timeout(v: String) -> Result<(), String> {
+    crate::time::parse_duration(&v)
+        .map(|_| ())
+        .ok_or_else(|| format!("'{}' is not a duration like 30s, 10m or 2h", v))
+}
+
+fn is_run(v: String) -> Result<(), String> {
+    v.parse::<u64>()
+        .map(|_| ())
+        .map_err(|_| format!("'{}' is not a run number", v))
+}
+
+fn is_revision(v: String) -> Result<(), String> {
+    v.parse::<u64>()
+        .map(|_| ())
+        .map_err(|_| format!("'{}' is not a revision number", v))
+}
@@ -0,0 +1,201 @@
+use std::path::{Component, Path};
+
+use serde_json;
+
+use crate::environment;
+use crate::error::{Error, Result, EXIT_BROKEN_DATA};
+use crate::params::Param;
+use crate::task::Task;
+
+// VERSION is bumped whenever Entry gains a field, so that an older chore refuses a bundle
+// rather than importing its tasks without what it doesn't know. Older bundles lack the new
+// fields, which take their defaults.
+pub const VERSION: u32 = 2;
+
+// Bundle is a portable set of tasks. Task paths are relative to the exported directory.
+#[derive(Serialize, Deserialize)]
+pub struct Bundle {
+    pub version: u32,
+    pub tasks: Vec<Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub path: String,
+    #[serde(default)]
+    pub inherit: bool,
+    #[serde(default)]
+    pub extension: String,
+    #[serde(default)]
+    pub interpreter: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<(String, String)>,
+    #[serde(default)]
+    pub cwd: String,
+    #[serde(default)]
+    pub timeout: String,
+    #[serde(default)]
+    pub watch: Vec<String>,
+    #[serde(default)]
+    pub schedule: String,
+    #[serde(default)]
+    pub depends: Vec<String>,
+    #[serde(default)]
+    pub params: Vec<Param>,
+    pub content: String,
+}
+
+impl Bundle {
+    pub fn new() -> Bundle {
+        Bundle {
+            version: VERSION,
+            tasks: Vec::new(),
+        }
+    }
+
+    pub fn from_slice(v: &[u8]) -> Result<Bundle> {
+        let bundle: Bundle = serde_json::from_slice(v).map_err(|e| {
+            Error::new(format!("The bundle is broken: {}", e)).with_code(EXIT_BROKEN_DATA)
+        })?;
+        if bundle.version > VERSION {
+            return Err(Error::with_suggest(
+                format!("Bundle version {} is not supported", bundle.version),
+                "Upgrade chore to import it".to_owned(),
+            )
+            .with_code(EXIT_BROKEN_DATA));
+        }
+        Ok(bundle)
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).unwrap()
+    }
+
+    pub fn push(&mut self, root: &Path, task: &Task) -> Result<()> {
+        let path = Path::new(task.path()).strip_prefix(root).unwrap();
+        let content = String::from_utf8(task.content().to_vec()).map_err(|_| {
+            Error::new(format!(
+                "Task '{}' is not a text, so it can't be exported",
+                task.path()
+            ))
+        })?;
+
+        self.tasks.push(Entry {
+            path: path.to_string_lossy().into_owned(),
+            inherit: task.inherit(),
+            extension: task.extension().to_owned(),
+            interpreter: task.interpreter().to_owned(),
+            description: task.description().to_owned(),
+            tags: task.tags().to_vec(),
+            env: task.env().to_vec(),
+            cwd: task.option("cwd")?,
+            timeout: task.option("timeout")?,
+            watch: task.watch().to_vec(),
+            schedule: task.option("schedule")?,
+            depends: task.depends().to_vec(),
+            params: task.params().to_vec(),
+            content,
+        });
+        Ok(())
+    }
+}
+
+impl Entry {
+    // to_task creates the task under root. The path must stay inside of root.
+    pub fn to_task(&self, root: &Path) -> Result<Task> {
+        let path = Path::new(&self.path);
+        let valid = path.components().count() > 0
+            && path.components().all(|c| matches!(c, Component::Normal(_)));
+        if !valid {
+            return Err(Error::new(format!(
+                "'{}' in the bundle is invalid path for a task",
+                self.path
+            )));
+        }
+
+        let mut task = Task::at(root.join(path));
+        task.set_inherit(self.inherit);
+        task.set_extension(&self.extension);
+        task.set_interpreter(&self.interpreter);
+        task.set_description(&self.description);
+        task.set_tags(&self.tags);
+        for (key, value) in &self.env {
+            let (key, value) =
+                environment::parse_pair(&format!("{}={}", key, value)).ok_or_else(|| {
+                    Error::new(format!(
+                        "'{}' in the bundle has an invalid variable '{}'",
+                        self.path, key
+                    ))
+                    .with_code(EXIT_BROKEN_DATA)
+                })?;
+            task.set_env(&key, &value);
+        }
+        task.set_option("cwd", &self.cwd)?;
+        task.set_option("timeout", &self.timeout)?;
+        task.set_option("watch", &self.watch.join(","))?;
+        task.set_option("schedule", &self.schedule)?;
+        task.set_option("depends", &self.depends.join(","))?;
+        task.set_params(self.params.clone())?;
+        task.set_content(self.content.clone().into_bytes());
+        Ok(task)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{Bundle, VERSION};
+    use crate::error::EXIT_BROKEN_DATA;
+    use crate::task::Task;
+
+    #[test]
+    fn tasks_come_back_under_another_directory() {
+        let mut task = Task::at("/a/tools/build");
+        task.set_extension("py");
+        task.set_content(b"print(1)".to_vec());
+        let mut bundle = Bundle::new();
+        bundle.push(Path::new("/a"), &task).unwrap();
+
+        let bundle = Bundle::from_slice(&bundle.to_vec()).unwrap();
+        let task = bundle.tasks[0].to_task(Path::new("/b")).unwrap();
+        assert_eq!(task.path(), "/b/tools/build");
+        assert_eq!(task.extension(), "py");
+        assert_eq!(task.content(), b"print(1)");
+    }
+
+    #[test]
+    fn paths_out_of_the_directory_are_rejected() {
+        for path in &["../x", "/x", "a/../../x", ""] {
+            let json = format!(
+                r#"{{"version":1,"tasks":[{{"path":"{}","content":""}}]}}"#,
+                path
+            );
+            let bundle = Bundle::from_slice(json.as_bytes()).unwrap();
+            assert!(
+                bundle.tasks[0].to_task(Path::new("/b")).is_err(),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn newer_bundles_are_refused() {
+        let json = format!(r#"{{"version":{},"tasks":[]}}"#, VERSION + 1);
+        let err = Bundle::from_slice(json.as_bytes()).err().unwrap();
+        assert_eq!(err.code(), EXIT_BROKEN_DATA);
+    }
+
+    #[test]
+    fn invalid_variables_are_rejected() {
+        let json = r#"{"version":1,"tasks":[{"path":"a","env":[["A B","1"]],"content":""}]}"#;
+        let bundle = Bundle::from_slice(json.as_bytes()).unwrap();
+        let err = bundle.tasks[0].to_task(Path::new("/b")).err().unwrap();
+        assert_eq!(err.code(), EXIT_BROKEN_DATA);
+    }
+}
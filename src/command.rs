@@ -1,15 +1,35 @@
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::env;
+use std::fmt;
 use std::fs::File;
-use std::io::{stdin, stdout, Cursor, Read, Write};
-use std::path::PathBuf;
-use std::process::{Child, Command};
+use std::io::{stdin, stdout, BufRead, BufReader, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use rand::{thread_rng, Rng};
-
-use crate::db::open_database;
-use crate::error::{Error, Result};
-use crate::task::{Task, TaskError, TaskSystem};
-use crate::tempfile::TempFile;
+use crate::bundle::Bundle;
+use crate::cron::Schedule;
+use crate::db::{open_database, open_existing_store, open_store, Config};
+use crate::diff;
+use crate::environment::Environment;
+use crate::error::{Error, Result, EXIT_FAILURE, EXIT_NOT_FOUND, EXIT_TIMEOUT, EXIT_USAGE};
+use crate::exec::{self, Script};
+use crate::graph;
+use crate::local;
+use crate::logs::{self, LogFile};
+use crate::params::{self, Parsed};
+use crate::path::normalize;
+use crate::schema;
+use crate::search;
+use crate::signal;
+use crate::task::{self, RunRecord, Task, TaskError, TaskSystem, OPTIONS};
+use crate::tempfile::{self, TempFile};
+use crate::time;
+use crate::watch::{self, Watcher};
 
 use colored::*;
 
@@ -29,24 +49,66 @@ fn validate_task_name(task: &str) -> Result<()> {
     Err(Error::with_suggest(
         format!("'{}' is invalid name for a task", task.yellow()),
         suggest,
-    ))
+    )
+    .with_code(EXIT_USAGE))
 }
 
 fn try_overwrite(task: &str) -> bool {
-    let (i, o) = (stdin(), stdout());
-
-    print!(
+    prompt(&format!(
         "Task '{}' already exists. Do you want to overwrite it? [y/n]: ",
         task.yellow(),
-    );
+    ))
+    .starts_with('y')
+}
+
+// prompt prints the question and returns the answer without the trailing newline.
+// An empty string is returned when stdin can't be read.
+fn prompt(question: &str) -> String {
+    let (i, o) = (stdin(), stdout());
+
+    print!("{}", question);
     if o.lock().flush().is_err() {
-        return false;
+        return String::new();
     }
 
     let mut input = String::new();
     match i.read_line(&mut input) {
-        Ok(_) => input.starts_with('y'),
-        _ => false,
+        Ok(_) => input.trim().to_owned(),
+        _ => String::new(),
+    }
+}
+
+fn open_task_system(config: &Config) -> Result<TaskSystem> {
+    let db = open_database(config)?;
+    let mut ts = TaskSystem::new(db);
+    ts.keep_revisions(config.revisions);
+    ts.keep_history(config.history);
+    Ok(ts)
+}
+
+#[derive(Clone, Copy)]
+pub enum Conflict {
+    Skip,
+    Overwrite,
+    Prompt,
+}
+
+impl Conflict {
+    pub fn from_name(name: &str) -> Conflict {
+        match name {
+            "skip" => Conflict::Skip,
+            "overwrite" => Conflict::Overwrite,
+            _ => Conflict::Prompt,
+        }
+    }
+
+    // accept decides whether an existing task should be replaced.
+    fn accept(self, task: &str) -> bool {
+        match self {
+            Conflict::Skip => false,
+            Conflict::Overwrite => true,
+            Conflict::Prompt => try_overwrite(task),
+        }
     }
 }
 
@@ -55,12 +117,14 @@ pub trait Cmd {
 }
 
 pub struct New {
+    pub db: Config,
     pub dir: PathBuf,
     pub task: String,
     pub inherit: bool,
     pub filename: String,
     pub src_task: String,
     pub ext: String,
+    pub interpreter: String,
     pub editor: Vec<String>,
 }
 
@@ -68,18 +132,17 @@ impl Cmd for New {
     fn run(&self) -> Result<()> {
         validate_task_name(&self.task)?;
 
-        let db = open_database().expect("unabled to open db");
-        let ts = TaskSystem::new(db);
+        let ts = open_task_system(&self.db)?;
 
-        if ts.exists(&self.task)? && !try_overwrite(&self.task) {
+        if ts.exists(self.dir.join(&self.task))? && !try_overwrite(&self.task) {
             return Ok(());
         }
 
         let mut task = Task::current(&self.task);
-        let mut file: Box<Read> = if !self.filename.is_empty() {
+        let mut file: Box<dyn Read> = if !self.filename.is_empty() {
             Box::new(File::open(&self.filename)?)
         } else if !self.src_task.is_empty() {
-            let task = ts.open(&self.src_task)?;
+            let task = open_task(&ts, &self.src_task)?;
             Box::new(Cursor::new(task.take()))
         } else {
             let mut file = create_tempfile(&self.ext).expect("failed to open temp file");
@@ -103,6 +166,7 @@ impl Cmd for New {
         let mut content = Vec::new();
         let _ = file.read_to_end(&mut content)?;
         task.set_extension(&self.ext);
+        task.set_interpreter(&self.interpreter);
         task.set_inherit(self.inherit);
         task.set_content(content);
         ts.save(&task)?;
@@ -113,19 +177,19 @@ impl Cmd for New {
 }
 
 pub struct Edit {
+    pub db: Config,
+    pub dir: PathBuf,
     pub task: String,
 }
 
 impl Cmd for Edit {
     fn run(&self) -> Result<()> {
-        validate_task_name(&self.task)?;
-
-        let db = open_database().expect("unabled to open db");
-        let ts = TaskSystem::new(db);
-        let mut task = match ts.open(&self.task) {
-            Ok(task) => task,
-            Err(e) => return Err(e.into()),
-        };
+        let ts = open_task_system(&self.db)?;
+        let mut task = resolve_task(&ts, &self.dir, &self.task)?;
+        in_database(&task)?;
+        if !confirm_resolved(&self.dir, &self.task, &task, "edit") {
+            return Ok(());
+        }
 
         let mut file = create_tempfile(task.extension()).expect("failed to open temp file");
         file.write_all(task.content())?;
@@ -150,193 +214,2322 @@ impl Cmd for Edit {
     }
 }
 
+// Run runs a task after its dependencies. With parallel, it runs all of tasks at once,
+// otherwise there is only one task which takes args.
 pub struct Run {
+    pub db: Config,
     pub dir: PathBuf,
-    pub task: String,
+    pub tasks: Vec<String>,
     pub args: Vec<String>,
+    pub env: Vec<String>,
+    pub dotenv: bool,
+    pub print_env: bool,
+    pub keep_going: bool,
+    pub parallel: bool,
+    pub jobs: usize,
+    pub log: bool,
+    // Seconds, which takes precedence over the timeout of the tasks.
+    pub timeout: Option<u64>,
+    // Globs of the files to rerun on. Empty for the watch option of the tasks.
+    pub watch: Option<Vec<String>>,
+    pub on_change: OnChange,
 }
 
 impl Cmd for Run {
     fn run(&self) -> Result<()> {
-        validate_task_name(&self.task)?;
+        for name in &self.tasks {
+            validate_task_name(name)?;
+        }
 
-        let mut dir = self.dir.clone();
-        let db = open_database().expect("unabled to open db");
-        let ts = TaskSystem::new(db);
+        match self.watch {
+            Some(ref globs) if !self.print_env => self.run_watching(globs),
+            _ => self.run_once(),
+        }
+    }
+}
+
+impl Run {
+    // The database is opened to read the tasks and closed before they run, so other
+    // commands can use it meanwhile. Each run is recorded by opening it again.
+    fn run_once(&self) -> Result<()> {
+        if self.parallel {
+            return self.run_parallel();
+        }
+        let (nodes, args, environment) = {
+            let ts = open_task_system(&self.db)?;
+            let task = resolve_task(&ts, &self.dir, &self.tasks[0])?;
+
+            let (args, environment) = match self.prepare(&task, &self.args)? {
+                Some(prepared) => prepared,
+                None => return Ok(()),
+            };
+            if self.print_env {
+                print_environment(&environment);
+                return Ok(());
+            }
+            (self.resolve(&ts, vec![task])?, args, environment)
+        };
+        if nodes.len() == 1 {
+            return self.spawn(&nodes[0].task, &args, &environment);
+        }
+
+        // Each task runs once, after all of its dependencies have succeeded.
+        let mut succeeded: Vec<bool> = Vec::new();
+        let mut failure = None;
+        for (i, node) in nodes.iter().enumerate() {
+            if let Some(&dep) = node.depends.iter().find(|&&dep| !succeeded[dep]) {
+                eprintln!(
+                    "{} {} since {} has failed",
+                    "Skip".yellow().bold(),
+                    node.task.name(),
+                    nodes[dep].task.name()
+                );
+                succeeded.push(false);
+                continue;
+            }
 
-        let mut is_cwd = true;
-        let task = loop {
-            let path = {
-                let mut dir = dir.clone();
-                dir.push(&self.task);
-                dir
+            eprintln!("{} {}", "==>".green().bold(), node.task.name());
+            let result = if i == nodes.len() - 1 {
+                self.spawn(&node.task, &args, &environment)
+            } else {
+                match self.prepare(&node.task, &[]) {
+                    Ok(Some((args, environment))) => self.spawn(&node.task, &args, &environment),
+                    Ok(None) => Ok(()),
+                    Err(e) => Err(e),
+                }
             };
 
-            match ts.open(&path) {
-                Ok(task) => {
-                    if is_cwd || task.inherit() {
-                        break task;
+            match result {
+                Ok(()) => succeeded.push(true),
+                Err(e) => {
+                    if !self.keep_going || signal::is_stopping() || signal::is_interrupted(e.code())
+                    {
+                        return Err(e);
+                    }
+                    if !e.is_silent() {
+                        eprintln!("{}", e);
                     }
+                    failure = failure.or(Some(e));
+                    succeeded.push(false);
                 }
-                Err(e) => match e {
-                    TaskError::NotFound(_) => (),
-                    _ => return Err(e.into()),
-                },
             }
+        }
 
-            if !dir.pop() {
-                return Err(TaskError::NotFound(self.task.clone()).into());
-            }
+        match failure {
+            Some(e) => Err(Error::exited(e.code())),
+            None => Ok(()),
+        }
+    }
 
-            is_cwd = false;
+    // run_watching runs the tasks again whenever the files they watch change, until it is
+    // interrupted. The files are under the directory of the first task and match globs,
+    // or the watch option of the tasks when no glob is given. Without either, every file
+    // is watched but the ones in output directories.
+    // The database is opened for each run, so it isn't kept from other commands while waiting.
+    fn run_watching(&self, globs: &[String]) -> Result<()> {
+        let roots = {
+            let ts = open_task_system(&self.db)?;
+            self.tasks
+                .iter()
+                .map(|name| resolve_task(&ts, &self.dir, name))
+                .collect::<Result<Vec<Task>>>()?
         };
+        let mut globs = globs.to_vec();
+        if globs.is_empty() {
+            for glob in roots.iter().flat_map(|t| t.watch()) {
+                if !globs.contains(glob) {
+                    globs.push(glob.clone());
+                }
+            }
+        }
+        for glob in &globs {
+            watch::check(glob)?;
+        }
+        let root = roots[0].dir().to_path_buf();
+        let mut watcher = Watcher::new(&root, &globs)?;
 
-        let mut file = create_tempfile(task.extension()).expect("failed to open temp file");
-        file.write_all(task.content())?;
-        file.close();
+        // With restart, a change stops the run at once. Otherwise it is run again after it ends.
+        let running = Arc::new(AtomicBool::new(false));
+        let (sender, changes) = mpsc::channel();
+        {
+            let running = running.clone();
+            let on_change = self.on_change;
+            thread::spawn(move || loop {
+                let changed = watcher.wait();
+                if let Ok(ref changed) = changed {
+                    if changed.is_empty() {
+                        continue;
+                    }
+                    if on_change == OnChange::Restart && running.load(Ordering::SeqCst) {
+                        signal::stop_all();
+                    }
+                }
+                let failed = changed.is_err();
+                if sender.send(changed).is_err() || failed {
+                    return;
+                }
+            });
+        }
 
-        Command::new("chmod")
-            .arg("+x")
-            .arg(file.path())
-            .spawn()?
-            .wait()?;
+        let watching = if globs.is_empty() {
+            "every file".to_owned()
+        } else {
+            globs.join(", ")
+        };
+        eprintln!(
+            "{} Watching {} in {}",
+            "==>".cyan().bold(),
+            watching,
+            root.to_string_lossy()
+        );
+        loop {
+            signal::resume();
+            running.store(true, Ordering::SeqCst);
+            let result = self.run_once();
+            running.store(false, Ordering::SeqCst);
+            if let Err(e) = result {
+                if signal::is_interrupted(e.code()) {
+                    return Err(e);
+                }
+                if !e.is_silent() {
+                    eprintln!("{}", e);
+                }
+            }
 
-        let mut cmd = Command::new(file.path());
-        let mut child: Child = cmd.arg(file.path()).args(&self.args).spawn()?;
-        let _ = child.wait()?;
-        Ok(())
+            // The changes which came during the run are taken at once.
+            let mut changed = changes
+                .recv()
+                .map_err(|_| Error::new("Stopped watching files"))??;
+            while let Ok(more) = changes.try_recv() {
+                changed.extend(more?);
+            }
+            eprintln!(
+                "{} {} changed",
+                "==>".cyan().bold(),
+                describe_changes(&root, &changed)
+            );
+        }
     }
 }
 
-pub struct Show {
-    pub task: String,
+// OnChange is what 'chore run --watch' does when files change during a run.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OnChange {
+    // Stop the run and start it again.
+    Restart,
+    // Start it again after it ends.
+    Queue,
 }
 
-impl Cmd for Show {
-    fn run(&self) -> Result<()> {
-        validate_task_name(&self.task)?;
-        let db = open_database().expect("unabled to open db");
-        let ts = TaskSystem::new(db);
-        let task: Task = ts.open(&self.task)?;
+impl OnChange {
+    pub fn from_name(name: &str) -> OnChange {
+        match name {
+            "queue" => OnChange::Queue,
+            _ => OnChange::Restart,
+        }
+    }
+}
 
-        println!("{}", "[options]".green().bold());
-        println!("inherit: {}", task.inherit().to_string().red());
-        println!("{}", "[content]".green().bold());
-        println!("{}", String::from_utf8_lossy(task.content()).to_owned());
-        Ok(())
+// describe_changes names the first of the changed files, like 'src/main.rs and 2 more'.
+fn describe_changes(root: &Path, changed: &[PathBuf]) -> String {
+    let first = match changed.first() {
+        Some(path) => path.strip_prefix(root).unwrap_or(path),
+        None => return "Nothing".to_owned(),
+    };
+    let first = if first.as_os_str().is_empty() {
+        "Something".to_owned()
+    } else {
+        first.to_string_lossy().into_owned()
+    };
+    match changed.len() {
+        1 => first,
+        n => format!("{} and {} more", first, n - 1),
     }
 }
 
-pub struct Remove {
-    pub task: String,
+#[derive(Clone, Copy, PartialEq)]
+enum JobState {
+    Waiting,
+    Running,
+    Succeeded,
+    Failed(i32),
+    Skipped,
 }
 
-impl Cmd for Remove {
-    fn run(&self) -> Result<()> {
-        validate_task_name(&self.task)?;
+// Colors of the task names in the output of parallel tasks.
+const JOB_COLORS: &[Color] = &[
+    Color::Cyan,
+    Color::Yellow,
+    Color::Green,
+    Color::Magenta,
+    Color::Blue,
+    Color::BrightCyan,
+    Color::BrightYellow,
+    Color::BrightGreen,
+    Color::BrightMagenta,
+    Color::BrightBlue,
+];
+
+impl Run {
+    // prepare builds the arguments and the environment the task runs with.
+    // A task with parameters has its arguments validated, the others get them as they are.
+    // None means the usage of the task was asked and printed.
+    fn prepare(&self, task: &Task, args: &[String]) -> Result<Option<(Vec<String>, Environment)>> {
+        let mut args = args.to_vec();
+        let mut values = Vec::new();
+        if !task.params().is_empty() {
+            match params::parse(task, &args, &self.dir)? {
+                Parsed::Help => {
+                    print!("{}", params::usage(task));
+                    return Ok(None);
+                }
+                Parsed::Arguments(parsed) => {
+                    args = parsed
+                        .values
+                        .iter()
+                        .map(|(_, value)| value.clone().unwrap_or_default())
+                        .chain(parsed.rest)
+                        .collect();
+                    values = parsed.values;
+                }
+            }
+        } else if args.first().map(|a| a == "--").unwrap_or(false) {
+            args.remove(0);
+        }
 
-        let db = open_database().expect("unabled to open db");
-        let ts = TaskSystem::new(db);
-        ts.remove(&self.task).map_err(|e| e.into())
+        let mut environment = Environment::inherited();
+        environment.extend_run(task, &self.dir);
+        environment.extend_task(task);
+        if self.dotenv {
+            environment.load_dotenv(&self.dir)?;
+        }
+        environment.extend_overrides(&self.env)?;
+        environment.extend_arguments(&values);
+        Ok(Some((args, environment)))
     }
-}
 
-pub struct List {
-    pub dir: PathBuf,
-}
+    // resolve finds the dependencies of the tasks and puts them in the order to run.
+    fn resolve(&self, ts: &TaskSystem, roots: Vec<Task>) -> Result<Vec<graph::Node>> {
+        graph::order(roots, |task| {
+            task.depends()
+                .iter()
+                .map(|name| find_task(ts, &self.dir, name))
+                .collect()
+        })
+    }
 
-impl Cmd for List {
-    fn run(&self) -> Result<()> {
-        let db = open_database()?;
-        let ts = TaskSystem::new(db);
+    // command makes the command which runs the task.
+    // The script has to be kept until the command exits.
+    fn command(
+        &self,
+        task: &Task,
+        args: &[String],
+        environment: &Environment,
+    ) -> Result<(Command, Script)> {
+        let script = Script::create(task.content(), task.extension())?;
+        let work_dir = task.work_dir(&self.dir);
+        if !work_dir.is_dir() {
+            return Err(Error::with_suggest(
+                format!(
+                    "'{}' to run the task in doesn't exist",
+                    work_dir.to_string_lossy().yellow()
+                ),
+                "Change it with 'chore set <task> cwd=...'".to_owned(),
+            )
+            .with_code(EXIT_NOT_FOUND));
+        }
+
+        let mut command = script.command(&exec::interpreter(task));
+        command.current_dir(&work_dir).args(args);
+        environment.apply(&mut command);
+        Ok((command, script))
+    }
+
+    fn spawn(&self, task: &Task, args: &[String], environment: &Environment) -> Result<()> {
+        let (mut command, _script) = self.command(task, args, environment)?;
+        let log = self.create_log(task)?;
+        let timeout = self.timeout(task);
+        let foreground = signal::is_foreground();
+        signal::isolate(&mut command, foreground);
+        let started_at = time::now();
+        let start = Instant::now();
+
+        // With a log, the output goes through chore, which writes it to both the terminal and the log.
+        let (exit, output) = match log {
+            Some(ref log) => {
+                let sink = Sink::new(None, Some(log.clone()));
+                let mut child = command
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .spawn()?;
+                let stdout = forward(child.stdout.take().unwrap(), false, sink.clone());
+                let stderr = forward(child.stderr.take().unwrap(), true, sink.clone());
+                let exit = signal::wait(&mut child, timeout, foreground)?;
+                let _ = stdout.join();
+                let _ = stderr.join();
+                (exit, sink.output())
+            }
+            None => {
+                let mut child: Child = command.spawn()?;
+                (signal::wait(&mut child, timeout, foreground)?, Vec::new())
+            }
+        };
+
+        let result = task_result(task.name(), &exit, timeout);
+        let code = result.as_ref().err().map(|e| e.code()).unwrap_or(0);
+        if let Some(ref log) = log {
+            log.finish(code);
+        }
+        let mut record = self.run_record(task, args, started_at, start.elapsed(), code);
+        record.output = output;
+        record.log = log.map(|l| l.number());
+        record_run(&self.db, task, &record);
+        result
+    }
+
+    // timeout is how long the task may run. --timeout takes precedence over the task,
+    // and 0 means no limit.
+    fn timeout(&self, task: &Task) -> Option<Duration> {
+        match self.timeout {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => task.timeout(),
+        }
+    }
+
+    // create_log starts the log of a run when it is asked for with --log.
+    fn create_log(&self, task: &Task) -> Result<Option<Arc<LogFile>>> {
+        if !self.log {
+            return Ok(None);
+        }
+        let log = LogFile::create(&self.db.logs, task.path())?;
+        Ok(Some(Arc::new(log)))
+    }
+
+    fn run_record(
+        &self,
+        task: &Task,
+        args: &[String],
+        started_at: u64,
+        duration: Duration,
+        exit_code: i32,
+    ) -> RunRecord {
+        RunRecord {
+            task: task.path().to_owned(),
+            cwd: self.dir.to_string_lossy().into_owned(),
+            args: args.to_vec(),
+            started_at,
+            duration_ms: duration.as_secs() * 1000 + u64::from(duration.subsec_millis()),
+            exit_code,
+            output: Vec::new(),
+            log: None,
+        }
+    }
 
-        let mut task_names = HashMap::new();
-        let mut dir: PathBuf = self.dir.clone();
-        let mut is_cwd = true;
+    // run_parallel runs the tasks and their dependencies at once, up to jobs at a time.
+    // A task starts when all of its dependencies have succeeded. Each line of the output
+    // is prefixed with the name of its task, and the status of every task is printed at the end.
+    fn run_parallel(&self) -> Result<()> {
+        let nodes = {
+            let ts = open_task_system(&self.db)?;
+            let roots = self
+                .tasks
+                .iter()
+                .map(|name| resolve_task(&ts, &self.dir, name))
+                .collect::<Result<Vec<Task>>>()?;
+            if self.print_env {
+                for task in &roots {
+                    if let Some((_, environment)) = self.prepare(task, &[])? {
+                        eprintln!("{} {}", "==>".green().bold(), task.name());
+                        print_environment(&environment);
+                    }
+                }
+                return Ok(());
+            }
+            self.resolve(&ts, roots)?
+        };
+        let jobs = if self.jobs == 0 {
+            nodes.len()
+        } else {
+            self.jobs
+        };
+        let width = nodes
+            .iter()
+            .map(|n| n.task.name().chars().count())
+            .max()
+            .unwrap_or(0);
 
+        let (sender, receiver) = mpsc::channel();
+        let mut states = vec![JobState::Waiting; nodes.len()];
+        let mut started_at = vec![0; nodes.len()];
+        let mut running = 0;
         loop {
-            let mut print_dir = false;
-            for task in ts.scan(&dir, true)? {
-                if task_names.get(task.name()).is_some() {
-                    // Only first found task is visible
+            for i in 0..nodes.len() {
+                let stopped = signal::is_stopping()
+                    || !self.keep_going && states.iter().any(|s| matches!(*s, JobState::Failed(_)));
+                if stopped || running >= jobs {
+                    break;
+                }
+                if states[i] != JobState::Waiting {
                     continue;
                 }
 
-                if is_cwd || task.inherit() {
-                    task_names.insert(task.name().to_owned(), true);
+                let depends: Vec<JobState> = nodes[i].depends.iter().map(|&d| states[d]).collect();
+                if depends
+                    .iter()
+                    .any(|s| matches!(*s, JobState::Failed(_) | JobState::Skipped))
+                {
+                    states[i] = JobState::Skipped;
+                    continue;
+                }
+                if depends.iter().any(|s| *s != JobState::Succeeded) {
+                    continue;
+                }
 
-                    if !print_dir {
-                        let mut message = format!("[{}", dir.to_string_lossy().green());
-                        if is_cwd {
-                            message.push_str(&format!(" {}", "(current)".red()));
+                let prefix = format!("{:1$} |", nodes[i].task.name(), width)
+                    .color(JOB_COLORS[i % JOB_COLORS.len()])
+                    .to_string();
+                match self.start(&nodes[i].task, i, prefix.clone(), sender.clone()) {
+                    Ok(()) => {
+                        states[i] = JobState::Running;
+                        started_at[i] = time::now();
+                        running += 1;
+                    }
+                    Err(e) => {
+                        if !e.is_silent() {
+                            eprint!("{} {}", prefix, e);
                         }
-                        message.push(']');
-                        println!("{}", message);
-                        print_dir = true;
+                        states[i] = JobState::Failed(e.code());
                     }
-                    println!("  {}", task.name(),);
                 }
             }
 
-            if !dir.pop() {
+            if running == 0 {
                 break;
             }
-            is_cwd = false;
+            let job: Job = receiver.recv().unwrap();
+            running -= 1;
+            let i = job.index;
+            states[i] = match job.result {
+                Ok(()) => JobState::Succeeded,
+                Err(ref e) => JobState::Failed(e.code()),
+            };
+            if let Some(duration) = job.duration {
+                let code = job.result.err().map(|e| e.code()).unwrap_or(0);
+                let mut record =
+                    self.run_record(&nodes[i].task, &[], started_at[i], duration, code);
+                record.output = job.output;
+                record.log = job.log;
+                record_run(&self.db, &nodes[i].task, &record);
+            }
+        }
+
+        let width = cmp::max(width, "TASK".len());
+        eprintln!();
+        eprintln!("{}", format!("{:1$}  STATUS", "TASK", width).bold());
+        for (node, state) in nodes.iter().zip(&states) {
+            let status = match *state {
+                JobState::Succeeded => "ok".green(),
+                JobState::Failed(code) => format!("failed ({})", code).red(),
+                JobState::Skipped => "skipped".yellow(),
+                JobState::Waiting | JobState::Running => "not run".yellow(),
+            };
+            eprintln!("{:1$}  {2}", node.task.name(), width, status);
+        }
+
+        let failure = states
+            .iter()
+            .filter_map(|s| match *s {
+                JobState::Failed(code) => Some(code),
+                _ => None,
+            })
+            .next();
+        match failure {
+            Some(code) => Err(Error::exited(code)),
+            None => Ok(()),
         }
+    }
+
+    // start spawns the task with its output piped through prefix, and reports the result
+    // to sender once it exits.
+    fn start(
+        &self,
+        task: &Task,
+        index: usize,
+        prefix: String,
+        sender: mpsc::Sender<Job>,
+    ) -> Result<()> {
+        let (args, environment) = match self.prepare(task, &[])? {
+            Some(prepared) => prepared,
+            None => {
+                let _ = sender.send(Job {
+                    index,
+                    result: Ok(()),
+                    duration: None,
+                    output: Vec::new(),
+                    log: None,
+                });
+                return Ok(());
+            }
+        };
+        let (mut command, script) = self.command(task, &args, &environment)?;
+        let log = self.create_log(task)?;
+        let timeout = self.timeout(task);
+        signal::isolate(&mut command, false);
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let start = Instant::now();
+        let sink = Sink::new(Some(prefix), log.clone());
+        let stdout = forward(child.stdout.take().unwrap(), false, sink.clone());
+        let stderr = forward(child.stderr.take().unwrap(), true, sink.clone());
+        let name = task.name().to_owned();
+        thread::spawn(move || {
+            let exit = signal::wait(&mut child, timeout, false);
+            let _ = stdout.join();
+            let _ = stderr.join();
+            drop(script);
+            let result = exit
+                .map_err(Error::from)
+                .and_then(|exit| task_result(&name, &exit, timeout));
+            if let Some(ref log) = log {
+                log.finish(result.as_ref().err().map(|e| e.code()).unwrap_or(0));
+            }
+            let _ = sender.send(Job {
+                index,
+                result,
+                duration: Some(start.elapsed()),
+                output: sink.output(),
+                log: log.map(|l| l.number()),
+            });
+        });
         Ok(())
     }
 }
 
-pub struct Rename {
-    pub from: String,
-    pub to: String,
+// Job is a finished task of run_parallel. The duration is None when it didn't run.
+struct Job {
+    index: usize,
+    result: Result<()>,
+    duration: Option<Duration>,
+    output: Vec<String>,
+    log: Option<u64>,
 }
 
-impl Cmd for Rename {
+// How many lines of the output are kept in the history.
+const OUTPUT_TAIL: usize = 20;
+
+// Sink is where the lines of the output of a task go: the terminal, with the prefix if any,
+// the log if any, and the tail which is kept in the history.
+#[derive(Clone)]
+struct Sink {
+    prefix: Option<String>,
+    log: Option<Arc<LogFile>>,
+    tail: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl Sink {
+    fn new(prefix: Option<String>, log: Option<Arc<LogFile>>) -> Sink {
+        Sink {
+            prefix,
+            log,
+            tail: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    fn output(&self) -> Vec<String> {
+        self.tail
+            .lock()
+            .map(|t| t.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+// forward passes every line read from the output of a task to the sink.
+fn forward<R: Read + Send + 'static>(
+    output: R,
+    is_stderr: bool,
+    sink: Sink,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(output);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => (),
+            }
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches('\n');
+            let printed = match sink.prefix {
+                Some(ref prefix) => format!("{} {}", prefix, text),
+                None => text.to_owned(),
+            };
+            if is_stderr {
+                eprintln!("{}", printed);
+            } else {
+                println!("{}", printed);
+            }
+            if let Some(ref log) = sink.log {
+                log.line(if is_stderr { "err" } else { "out" }, text);
+            }
+            if let Ok(mut tail) = sink.tail.lock() {
+                if tail.len() == OUTPUT_TAIL {
+                    tail.pop_front();
+                }
+                tail.push_back(text.to_owned());
+            }
+        }
+    })
+}
+
+pub struct History {
+    pub db: Config,
+    pub dir: PathBuf,
+    pub task: Option<String>,
+    pub failed: bool,
+    pub since: Option<u64>,
+    pub count: usize,
+    pub output: bool,
+}
+
+impl Cmd for History {
     fn run(&self) -> Result<()> {
-        validate_task_name(&self.from)?;
-        validate_task_name(&self.to)?;
+        let ts = open_task_system(&self.db)?;
 
-        let db = open_database()?;
-        let ts = TaskSystem::new(db);
+        // The task is looked up like 'chore run' does. The runs of a removed one are found by
+        // its path in the current directory, not by the name alone which tasks elsewhere share.
+        let path = match self.task {
+            Some(ref name) => match find_task(&ts, &self.dir, name) {
+                Ok(task) => Some(task.path().to_owned()),
+                Err(ref e) if e.code() == EXIT_NOT_FOUND => None,
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
 
-        let from_task = ts.open(&self.from)?;
-        if ts.exists(&self.to)? && !try_overwrite(&self.to) {
-            return Ok(());
+        let records: Vec<RunRecord> = ts
+            .history()?
+            .into_iter()
+            .filter(|r| match (&path, &self.task) {
+                (Some(path), _) => &r.task == path,
+                (None, Some(name)) => Path::new(&r.task) == self.dir.join(name),
+                (None, None) => true,
+            })
+            .filter(|r| !self.failed || r.exit_code != 0)
+            .filter(|r| {
+                self.since
+                    .map(|since| r.started_at >= since)
+                    .unwrap_or(true)
+            })
+            .collect();
+        let skip = if self.count > 0 {
+            records.len().saturating_sub(self.count)
+        } else {
+            0
+        };
+
+        for record in &records[skip..] {
+            let status = if record.exit_code == 0 {
+                format!("{:8}", "ok").green()
+            } else {
+                format!("{:8}", format!("exit {}", record.exit_code)).red()
+            };
+            let log = match record.log {
+                Some(n) => format!(" (log {})", n).cyan().to_string(),
+                None => String::new(),
+            };
+            println!(
+                "{}  {:>7}  {}  {}{}{}",
+                time::format(record.started_at),
+                time::format_duration(record.duration_ms),
+                status,
+                record.task,
+                record
+                    .args
+                    .iter()
+                    .map(|a| format!(" {}", a))
+                    .collect::<String>(),
+                log
+            );
+            if self.output {
+                for line in &record.output {
+                    println!("    {}", line);
+                }
+            }
         }
+        Ok(())
+    }
+}
 
-        let mut to_task = Task::current(&self.to);
-        to_task.copy_from(&from_task);
+pub struct Logs {
+    pub db: Config,
+    pub dir: PathBuf,
+    pub task: String,
+    pub run: Option<u64>,
+    pub follow: bool,
+}
 
-        let mut batch = ts.batch();
-        batch.save(&to_task)?;
-        batch.remove_task(from_task)?;
-        batch.commit()?;
+impl Cmd for Logs {
+    fn run(&self) -> Result<()> {
+        // The database is closed before the log is read, so a followed run can record itself.
+        // The logs of a removed task are still found by its path in the current directory.
+        let path = {
+            let ts = open_task_system(&self.db)?;
+            match find_task(&ts, &self.dir, &self.task) {
+                Ok(task) => task.path().to_owned(),
+                Err(ref e) if e.code() == EXIT_NOT_FOUND => {
+                    self.dir.join(&self.task).to_string_lossy().into_owned()
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        let runs = logs::runs(&self.db.logs, &path)?;
+        let number = match (self.run, runs.last()) {
+            (Some(n), _) if runs.contains(&n) => n,
+            (Some(n), Some(_)) => {
+                return Err(Error::with_suggest(
+                    format!("There is no log of run {} of '{}'", n, self.task),
+                    format!(
+                        "The kept logs are {}",
+                        runs.iter()
+                            .map(|n| n.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    ),
+                )
+                .with_code(EXIT_NOT_FOUND))
+            }
+            (None, Some(&last)) => last,
+            (_, None) => {
+                return Err(Error::with_suggest(
+                    format!("There is no log of '{}'", self.task),
+                    format!("Write one with 'chore run --log {}'", self.task),
+                )
+                .with_code(EXIT_NOT_FOUND))
+            }
+        };
+
+        let file = File::open(logs::path(&self.db.logs, &path, number))?;
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        let out = stdout();
+        let mut out = out.lock();
+        loop {
+            // A line which is being written is kept until it is complete.
+            reader.read_line(&mut line)?;
+            if line.ends_with('\n') {
+                out.write_all(line.as_bytes())?;
+                if logs::is_end(&line) {
+                    break;
+                }
+                line.clear();
+                continue;
+            }
+
+            if !self.follow {
+                out.write_all(line.as_bytes())?;
+                break;
+            }
+            out.flush()?;
+            thread::sleep(Duration::from_millis(200));
+        }
         Ok(())
     }
 }
 
-fn create_tempfile(ext: &str) -> Result<TempFile> {
-    let length = 8;
-    let charset = b"abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+// How often the daemon reads the schedules again, so changes are picked up without a restart.
+const RELOAD_INTERVAL: u64 = 30;
+
+// Daemon runs the tasks of the database on their schedules until it is stopped.
+// A due task is run by 'chore run --log' in its directory, so the run is recorded in the history
+// and its output in a log. The database is opened only to read the schedules, and not while
+// the tasks run.
+pub struct Daemon {
+    pub db: Config,
+}
+
+impl Cmd for Daemon {
+    fn run(&self) -> Result<()> {
+        let exe = env::current_exe()?;
+        let mut tasks = scheduled_tasks(&self.db)?;
+        let mut next: HashMap<String, (Schedule, Option<u64>)> = HashMap::new();
+        let mut running: Vec<(String, Child)> = Vec::new();
+        let mut loaded_at = time::now();
+        print_event(
+            &"start".green(),
+            &format!("{} scheduled tasks", tasks.len()),
+        );
+
+        loop {
+            running = running
+                .into_iter()
+                .filter_map(|(path, mut child)| match child.try_wait() {
+                    Ok(Some(status)) => {
+                        let event = match exit_code(status) {
+                            0 => "ok".green(),
+                            code => format!("exit {}", code).red(),
+                        };
+                        print_event(&event, &path);
+                        None
+                    }
+                    _ => Some((path, child)),
+                })
+                .collect();
+
+            let now = time::now();
+            if now >= loaded_at + RELOAD_INTERVAL {
+                // The database may be busy with another command.
+                // The schedules are kept until the next time.
+                match scheduled_tasks(&self.db) {
+                    Ok(loaded) => tasks = loaded,
+                    Err(e) => print_event(&"warning".yellow(), &e.to_string()),
+                }
+                loaded_at = now;
+            }
+
+            next.retain(|path, _| tasks.iter().any(|(t, _)| t.path() == path));
+            for (task, schedule) in &tasks {
+                let entry = next
+                    .entry(task.path().to_owned())
+                    .or_insert_with(|| (schedule.clone(), schedule.next_after(now)));
+                if entry.0 != *schedule {
+                    *entry = (schedule.clone(), schedule.next_after(now));
+                }
+                match entry.1 {
+                    Some(due) if due <= now => (),
+                    _ => continue,
+                }
+                entry.1 = schedule.next_after(now);
+
+                if running.iter().any(|(path, _)| path == task.path()) {
+                    print_event(
+                        &"skip".yellow(),
+                        &format!("{} is still running", task.path()),
+                    );
+                    continue;
+                }
+                match self.start(&exe, task) {
+                    Ok(child) => {
+                        print_event(&"run".cyan(), task.path());
+                        running.push((task.path().to_owned(), child));
+                    }
+                    Err(e) => print_event(&"error".red(), &format!("{}: {}", task.path(), e)),
+                }
+            }
 
-    let mut rng = thread_rng();
-    let mut file_name = ".chore".to_string();
+            // Wake up for the next due task, to reload or to notice a finished run.
+            let wake = next
+                .values()
+                .filter_map(|&(_, due)| due)
+                .chain(Some(loaded_at + RELOAD_INTERVAL))
+                .min()
+                .unwrap_or(now)
+                .saturating_sub(time::now());
+            let wake = if running.is_empty() {
+                wake
+            } else {
+                cmp::min(wake, 1)
+            };
+            thread::sleep(Duration::from_secs(cmp::max(wake, 1)));
+        }
+    }
+}
 
-    for _ in 0..length {
-        file_name.push(*rng.choose(charset).unwrap() as char);
+impl Daemon {
+    fn start(&self, exe: &Path, task: &Task) -> Result<Child> {
+        let child = Command::new(exe)
+            .arg("run")
+            .arg("--log")
+            .arg(task.name())
+            .current_dir(task.dir())
+            .env("CHORE_DB", &self.db.path)
+            .env("CHORE_BACKEND", self.db.backend.name())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        Ok(child)
     }
+}
+
+pub struct ScheduleList {
+    pub db: Config,
+}
+
+impl Cmd for ScheduleList {
+    fn run(&self) -> Result<()> {
+        let now = time::now();
+        let mut rows: Vec<(Option<u64>, String, String)> = scheduled_tasks(&self.db)?
+            .into_iter()
+            .map(|(task, schedule)| {
+                let expression = task.option("schedule").unwrap_or_default();
+                (schedule.next_after(now), expression, task.path().to_owned())
+            })
+            .collect();
+        // The tasks which never run come last.
+        rows.sort_by_key(|&(next, _, ref path)| (next.is_none(), next, path.clone()));
 
-    if !ext.is_empty() {
-        if !ext.starts_with('.') {
-            file_name.push('.');
+        let width = rows.iter().map(|r| r.1.chars().count()).max().unwrap_or(0);
+        for (next, expression, path) in rows {
+            let next = match next {
+                Some(next) => time::format_local(next),
+                None => format!("{:16}", "never"),
+            };
+            println!("{}  {:3$}  {}", next.cyan(), expression, path, width);
         }
-        file_name.push_str(ext);
+        Ok(())
     }
+}
 
-    TempFile::create(file_name)
+// scheduled_tasks returns the tasks of the database which have a schedule. Task files
+// can't have one, see local::load.
+fn scheduled_tasks(db: &Config) -> Result<Vec<(Task, Schedule)>> {
+    let ts = open_task_system(db)?;
+    Ok(ts
+        .records()?
+        .into_iter()
+        .filter_map(|(_, task)| task.ok())
+        .filter_map(|task| task.schedule().map(|schedule| (task, schedule)))
+        .collect())
 }
 
-fn print_done(message: &str) {
-    println!("    {} {}", "Done".green().bold(), message)
+fn print_event<S: fmt::Display + ?Sized>(event: &S, message: &str) {
+    println!("{} {} {}", time::format_local(time::now()), event, message);
+}
+
+// record_run opens the database to append the run to the history, and to keep when a task
+// of the database was last run. A failure to record doesn't fail the run.
+fn record_run(db: &Config, task: &Task, record: &RunRecord) {
+    let recorded = open_task_system(db).and_then(|ts| {
+        if task.source().is_none() {
+            ts.set_last_run(task, record.started_at)?;
+        }
+        ts.record_run(record)?;
+        Ok(())
+    });
+    if let Err(e) = recorded {
+        eprintln!(
+            "{} Unable to record the run: {}",
+            "warning:".yellow().bold(),
+            e
+        );
+    }
+}
+
+fn print_environment(environment: &Environment) {
+    for (key, value, origin) in environment.vars() {
+        println!("{}={} {}", key, value, format!("({})", origin).cyan());
+    }
+}
+
+// find_task looks for the task from dir up to the root, as 'chore run' does.
+// A task in a parent directory is visible only when it is inherited.
+fn find_task(ts: &TaskSystem, start: &Path, name: &str) -> Result<Task> {
+    validate_task_name(name)?;
+
+    let mut dir = start.to_path_buf();
+    let mut is_cwd = true;
+    loop {
+        if let Some(task) = task_at(ts, &dir, name)? {
+            if is_cwd || task.inherit() {
+                return Ok(task);
+            }
+        }
+
+        if !dir.pop() {
+            return Err(not_found(ts, start, name));
+        }
+
+        is_cwd = false;
+    }
+}
+
+// task_at returns the task of the name in dir. A task in the database takes precedence over
+// the task file in the same directory.
+fn task_at(ts: &TaskSystem, dir: &Path, name: &str) -> Result<Option<Task>> {
+    match ts.open(dir.join(name)) {
+        Ok(task) => Ok(Some(task)),
+        Err(TaskError::NotFound(_)) => local::find(dir, name),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// resolve_task finds the task the user means by name, for 'chore run', 'show', 'edit' and 'rm'.
+// Tasks and aliases are looked up from dir up to the root like find_task, where a task takes
+// precedence over an alias in the same directory. When there is neither, name may be the prefix
+// of only one visible task or alias.
+fn resolve_task(ts: &TaskSystem, dir: &Path, name: &str) -> Result<Task> {
+    validate_task_name(name)?;
+    if let Some(task) = lookup(ts, dir, name)? {
+        return Ok(task);
+    }
+
+    let mut names: Vec<String> = visible_tasks(ts, dir)?
+        .iter()
+        .flat_map(|g| g.2.iter())
+        .map(|t| t.name().to_owned())
+        .chain(
+            visible_aliases(ts, dir)?
+                .iter()
+                .map(|a| a.name().to_owned()),
+        )
+        .filter(|n| n.starts_with(name))
+        .collect();
+    names.sort();
+    names.dedup();
+
+    // An alias and its task are the same one.
+    let mut matched: Vec<(String, Task)> = Vec::new();
+    for candidate in names {
+        if let Some(task) = lookup(ts, dir, &candidate)? {
+            if matched.iter().all(|m| m.1.path() != task.path()) {
+                matched.push((candidate, task));
+            }
+        }
+    }
+    match matched.len() {
+        0 => Err(not_found(ts, dir, name)),
+        1 => Ok(matched.remove(0).1),
+        _ => {
+            let candidates: Vec<String> = matched.iter().map(|m| m.0.green().to_string()).collect();
+            Err(Error::with_suggest(
+                format!("'{}' matches more than one task", name.yellow()),
+                format!("Did you mean one of {}?", candidates.join(", ")),
+            )
+            .with_code(EXIT_USAGE))
+        }
+    }
+}
+
+// lookup finds the visible task or alias of the name from dir up to the root.
+fn lookup(ts: &TaskSystem, start: &Path, name: &str) -> Result<Option<Task>> {
+    let mut dir = start.to_path_buf();
+    let mut is_cwd = true;
+    loop {
+        if let Some(task) = task_at(ts, &dir, name)? {
+            if is_cwd || task.inherit() {
+                return Ok(Some(task));
+            }
+        }
+        if let Some(alias) = ts.alias(dir.join(name))? {
+            if is_cwd || alias.inherit() {
+                return alias_target(ts, &alias).map(Some);
+            }
+        }
+
+        if !dir.pop() {
+            return Ok(None);
+        }
+        is_cwd = false;
+    }
+}
+
+// alias_target finds the task of the alias from the directory of the alias.
+fn alias_target(ts: &TaskSystem, alias: &task::Alias) -> Result<Task> {
+    find_task(ts, alias.dir(), alias.target()).map_err(|e| {
+        if e.code() != EXIT_NOT_FOUND {
+            return e;
+        }
+        Error::with_suggest(
+            format!(
+                "Alias '{}' stands for '{}', which doesn't exist",
+                alias.name().yellow(),
+                alias.target().yellow()
+            ),
+            format!(
+                "You can point it to another task with 'chore alias {} <task>' in {}",
+                alias.name(),
+                alias.dir().to_string_lossy().green()
+            ),
+        )
+        .with_code(EXIT_NOT_FOUND)
+    })
+}
+
+// visible_aliases returns the aliases visible from dir, nearest first, the same way as tasks.
+fn visible_aliases(ts: &TaskSystem, dir: &Path) -> Result<Vec<task::Alias>> {
+    let mut visible: Vec<task::Alias> = Vec::new();
+    let mut dir = dir.to_path_buf();
+    let mut is_cwd = true;
+    loop {
+        for alias in ts.aliases(&dir)? {
+            if (is_cwd || alias.inherit()) && visible.iter().all(|a| a.name() != alias.name()) {
+                visible.push(alias);
+            }
+        }
+        if !dir.pop() {
+            return Ok(visible);
+        }
+        is_cwd = false;
+    }
+}
+
+pub struct Show {
+    pub db: Config,
+    pub dir: PathBuf,
+    pub task: String,
+}
+
+impl Cmd for Show {
+    fn run(&self) -> Result<()> {
+        let ts = open_task_system(&self.db)?;
+        let task: Task = resolve_task(&ts, &self.dir, &self.task)?;
+
+        println!("{}", "[options]".green().bold());
+        println!("path: {}", task.path());
+        if let Some(source) = task.source() {
+            println!("file: {}", source.to_string_lossy());
+        }
+        for key in OPTIONS {
+            println!("{}: {}", key, task.option(key)?.red());
+        }
+        println!("command: {}", command_line(&task, &[]));
+        println!("created: {}", format_time(task.created_at()));
+        println!("updated: {}", format_time(task.updated_at()));
+        println!("last run: {}", format_time(ts.last_run_at(&task)?));
+        println!("{}", "[content]".green().bold());
+        println!("{}", String::from_utf8_lossy(task.content()));
+        Ok(())
+    }
+}
+
+pub struct Set {
+    pub db: Config,
+    pub task: String,
+    pub options: Vec<String>,
+}
+
+impl Cmd for Set {
+    fn run(&self) -> Result<()> {
+        validate_task_name(&self.task)?;
+        let ts = open_task_system(&self.db)?;
+        let mut task = open_task(&ts, &self.task)?;
+
+        for option in &self.options {
+            let mut kv = option.splitn(2, '=');
+            let key = kv.next().unwrap_or_default();
+            let value = match kv.next() {
+                Some(value) => value,
+                None => {
+                    return Err(Error::with_suggest(
+                        format!("'{}' is not a key=value pair", option.yellow()),
+                        "For example, chore set build interpreter=\"bash -eu\"".to_owned(),
+                    )
+                    .with_code(EXIT_USAGE))
+                }
+            };
+            task.set_option(key, value)?;
+        }
+
+        ts.save(&task)?;
+        print_done("The task is updated successfully!");
+        Ok(())
+    }
+}
+
+pub struct Unset {
+    pub db: Config,
+    pub task: String,
+    pub keys: Vec<String>,
+}
+
+impl Cmd for Unset {
+    fn run(&self) -> Result<()> {
+        validate_task_name(&self.task)?;
+        let ts = open_task_system(&self.db)?;
+        let mut task = open_task(&ts, &self.task)?;
+
+        for key in &self.keys {
+            task.unset_option(key)?;
+        }
+
+        ts.save(&task)?;
+        print_done("The task is updated successfully!");
+        Ok(())
+    }
+}
+
+pub struct Remove {
+    pub db: Config,
+    pub dir: PathBuf,
+    pub task: String,
+}
+
+impl Cmd for Remove {
+    fn run(&self) -> Result<()> {
+        let ts = open_task_system(&self.db)?;
+        let task = resolve_task(&ts, &self.dir, &self.task)?;
+        in_database(&task)?;
+
+        if !confirm_resolved(&self.dir, &self.task, &task, "remove") {
+            return Ok(());
+        }
+        ts.remove(task.path()).map_err(|e| e.into())
+    }
+}
+
+// Alias makes an alias of the task in the current directory, or removes it.
+// Without a name, it prints the visible aliases.
+pub struct Alias {
+    pub db: Config,
+    pub dir: PathBuf,
+    pub name: Option<String>,
+    pub task: Option<String>,
+    pub inherit: bool,
+    pub delete: bool,
+}
+
+impl Cmd for Alias {
+    fn run(&self) -> Result<()> {
+        let ts = open_task_system(&self.db)?;
+        let name = match self.name {
+            Some(ref name) => name,
+            None => return self.print(&ts),
+        };
+        validate_task_name(name)?;
+
+        let path = self.dir.join(name);
+        if self.delete {
+            if ts.alias(&path)?.is_none() {
+                return Err(Error::with_suggest(
+                    format!("Alias '{}' doesn't exist", name.yellow()),
+                    "An alias is removed in the directory where it is made".to_owned(),
+                )
+                .with_code(EXIT_NOT_FOUND));
+            }
+            ts.remove_alias(&path)?;
+            print_done("The alias is removed successfully!");
+            return Ok(());
+        }
+
+        let target = match self.task {
+            Some(ref target) => resolve_task(&ts, &self.dir, target)?,
+            None => {
+                let task = resolve_task(&ts, &self.dir, name)?;
+                println!("{} -> {}", name, task.path());
+                return Ok(());
+            }
+        };
+        // A task takes precedence over an alias in the same directory.
+        if task_at(&ts, &self.dir, name)?.is_some() {
+            return Err(Error::with_suggest(
+                format!("Task '{}' already exists", name.yellow()),
+                "An alias can't have the name of a task in the same directory".to_owned(),
+            )
+            .with_code(EXIT_USAGE));
+        }
+
+        ts.save_alias(&task::Alias::new(&path, target.name(), self.inherit))?;
+        print_done("The alias is created successfully!");
+        Ok(())
+    }
+}
+
+impl Alias {
+    fn print(&self, ts: &TaskSystem) -> Result<()> {
+        let aliases = visible_aliases(ts, &self.dir)?;
+        let width = aliases
+            .iter()
+            .map(|a| a.name().chars().count())
+            .max()
+            .unwrap_or(0);
+        for alias in aliases {
+            let mut line = format!("{:2$} -> {}", alias.name(), alias.target(), width);
+            if alias.dir() != self.dir {
+                line.push_str(&format!(
+                    " {}",
+                    format!("({})", alias.dir().to_string_lossy()).cyan()
+                ));
+            }
+            println!("{}", line);
+        }
+        Ok(())
+    }
+}
+
+// in_database checks that the task can be changed by chore, which isn't from a task file.
+fn in_database(task: &Task) -> Result<()> {
+    match task.source() {
+        Some(source) => Err(Error::with_suggest(
+            format!("Task '{}' is defined in a task file", task.name().yellow()),
+            format!("You can change it in {}", source.to_string_lossy().green()),
+        )
+        .with_code(EXIT_USAGE)),
+        None => Ok(()),
+    }
+}
+
+// confirm_resolved asks before doing action to a task which was resolved from another name
+// or directory, which may not be what is meant.
+fn confirm_resolved(dir: &Path, name: &str, task: &Task, action: &str) -> bool {
+    Path::new(task.path()) == dir.join(name)
+        || prompt(&format!(
+            "Do you want to {} '{}'? [y/n]: ",
+            action,
+            task.path().yellow()
+        ))
+        .starts_with('y')
+}
+
+pub struct List {
+    pub db: Config,
+    pub dir: PathBuf,
+    pub long: bool,
+    pub tag: Option<String>,
+}
+
+impl Cmd for List {
+    fn run(&self) -> Result<()> {
+        let ts = open_task_system(&self.db)?;
+
+        // A filtered task still hides the ones of the same name above.
+        let mut groups = visible_tasks(&ts, &self.dir)?;
+        if let Some(ref tag) = self.tag {
+            for group in &mut groups {
+                group.2.retain(|t| t.has_tag(tag));
+            }
+            groups.retain(|g| !g.2.is_empty());
+        }
+
+        let width = |f: &dyn Fn(&Task) -> usize| {
+            groups
+                .iter()
+                .flat_map(|g| g.2.iter())
+                .map(f)
+                .max()
+                .unwrap_or(0)
+        };
+        let name_width = width(&|t| t.name().chars().count());
+        let tags_width = width(&|t| t.tags().join(",").chars().count());
+
+        for (dir, is_cwd, tasks) in groups {
+            let mut message = format!("[{}", dir.to_string_lossy().green());
+            if is_cwd {
+                message.push_str(&format!(" {}", "(current)".red()));
+            }
+            message.push(']');
+            println!("{}", message);
+
+            for task in tasks {
+                let source = match task.source() {
+                    Some(_) => format!(" {}", format!("({})", local::FILE_NAME).cyan()),
+                    None => String::new(),
+                };
+                if !self.long {
+                    println!("  {}{}", task.name(), source);
+                    continue;
+                }
+
+                // Colors are applied after padding, since escape codes would break alignment.
+                let line = format!(
+                    "  {:name_width$}  {}  {:19}  {:19}  {}{}",
+                    task.name(),
+                    format!("{:1$}", task.tags().join(","), tags_width).yellow(),
+                    format_time(task.updated_at()),
+                    format_time(ts.last_run_at(&task)?),
+                    task.description(),
+                    source,
+                    name_width = name_width
+                );
+                println!("{}", line.trim_end());
+            }
+        }
+        Ok(())
+    }
+}
+
+// visible_tasks returns the tasks visible from dir grouped by directory, from dir to the root,
+// with whether the group is of dir. Only the first found task of a name is visible.
+fn visible_tasks(ts: &TaskSystem, dir: &Path) -> Result<Vec<(PathBuf, bool, Vec<Task>)>> {
+    let mut groups = Vec::new();
+    let mut task_names = HashMap::new();
+    let mut dir = dir.to_path_buf();
+    let mut is_cwd = true;
+
+    loop {
+        let mut visible = Vec::new();
+        let local_tasks = local::load(&dir)?;
+        for task in ts.scan(&dir, true)?.chain(local_tasks) {
+            if task_names.contains_key(task.name()) {
+                continue;
+            }
+
+            if is_cwd || task.inherit() {
+                task_names.insert(task.name().to_owned(), true);
+                visible.push(task);
+            }
+        }
+        if !visible.is_empty() {
+            groups.push((dir.clone(), is_cwd, visible));
+        }
+
+        if !dir.pop() {
+            break;
+        }
+        is_cwd = false;
+    }
+    Ok(groups)
+}
+
+// open_task opens the task of the name in the current directory.
+fn open_task(ts: &TaskSystem, name: &str) -> Result<Task> {
+    let dir = env::current_dir()?;
+    ts.open(name).map_err(|e| explain(ts, &dir, e))
+}
+
+// explain turns an error of looking up a task from dir into one which tells why it's missing.
+fn explain(ts: &TaskSystem, dir: &Path, err: TaskError) -> Error {
+    match err {
+        TaskError::NotFound(ref name) if !name.contains('/') => not_found(ts, dir, name),
+        err => err.into(),
+    }
+}
+
+// not_found builds the error for a task which isn't visible from dir. It points out the tasks
+// of the name which are nearby but not visible, and otherwise the visible tasks of similar names.
+fn not_found(ts: &TaskSystem, dir: &Path, name: &str) -> Error {
+    let message = format!("Task '{}' doesn't exist", name.yellow());
+    let mut suggests = Vec::new();
+
+    // Above, a task is visible only when it is inherited, and then it is changed only there.
+    let mut inherited = None;
+    let mut hidden = Vec::new();
+    let mut hidden_alias = None;
+    let mut parent = dir.to_path_buf();
+    while parent.pop() {
+        if let Ok(Some(task)) = task_at(ts, &parent, name) {
+            let dir = task.dir().to_string_lossy().green().to_string();
+            if !task.inherit() {
+                hidden.push(dir);
+            } else if inherited.is_none() {
+                inherited = Some(dir);
+            }
+        }
+        match ts.alias(parent.join(name)) {
+            Ok(Some(ref alias)) if !alias.inherit() && hidden_alias.is_none() => {
+                hidden_alias = Some(alias.clone())
+            }
+            _ => (),
+        }
+    }
+    if let Some(alias) = hidden_alias {
+        suggests.push(format!(
+            "There is an alias of '{}' in {} without inherit. You can share it by 'chore alias {} {} --inherit' there",
+            alias.target(),
+            alias.dir().to_string_lossy().green(),
+            name,
+            alias.target()
+        ));
+    }
+    if let Some(dir) = inherited {
+        suggests.push(format!(
+            "It is inherited from {}, where it can be changed",
+            dir
+        ));
+    }
+    if !hidden.is_empty() {
+        suggests.push(format!(
+            "It is in {} without inherit. You can share it with subdirectories by 'chore set {} inherit=true' there",
+            hidden.join(", "),
+            name
+        ));
+    }
+
+    // Aside and below, a task is visible only from its directory and the subdirectories.
+    let around = dir.parent().unwrap_or(dir);
+    let mut elsewhere: Vec<PathBuf> = ts
+        .scan(around, false)
+        .map(|tasks| {
+            tasks
+                .filter(|t| t.name() == name && t.dir() != around)
+                .map(|t| t.dir().to_path_buf())
+                .collect()
+        })
+        .unwrap_or_default();
+    for subdir in subdirs(around).into_iter().chain(subdirs(dir)) {
+        if let Ok(Some(_)) = local::find(&subdir, name) {
+            elsewhere.push(subdir);
+        }
+    }
+    elsewhere.sort();
+    elsewhere.dedup();
+    if !elsewhere.is_empty() {
+        let dirs: Vec<String> = elsewhere
+            .iter()
+            .map(|d| d.to_string_lossy().green().to_string())
+            .collect();
+        suggests.push(format!(
+            "It is in {}, which is visible only from there and the subdirectories",
+            dirs.join(", ")
+        ));
+    }
+
+    let groups = visible_tasks(ts, dir).unwrap_or_default();
+    let aliases = visible_aliases(ts, dir).unwrap_or_default();
+    let names: Vec<&str> = groups
+        .iter()
+        .flat_map(|g| g.2.iter())
+        .map(|t| t.name())
+        .chain(aliases.iter().map(|a| a.name()))
+        .collect();
+    let similar: Vec<String> = search::similar(name, &names, 3)
+        .into_iter()
+        .map(|n| n.green().to_string())
+        .collect();
+    match similar.len() {
+        0 if suggests.is_empty() => {
+            suggests.push("You can list the available tasks with 'chore ls'".to_owned())
+        }
+        0 => (),
+        1 => suggests.push(format!("Did you mean {}?", similar[0])),
+        n => suggests.push(format!(
+            "Did you mean {} or {}?",
+            similar[..n - 1].join(", "),
+            similar[n - 1]
+        )),
+    }
+
+    Error::with_suggest(message, suggests.join("\n\t")).with_code(EXIT_NOT_FOUND)
+}
+
+// subdirs returns the directories in dir, except the hidden ones.
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    match dir.read_dir() {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+            .map(|e| e.path())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub struct Rename {
+    pub db: Config,
+    pub from: String,
+    pub to: String,
+}
+
+impl Cmd for Rename {
+    fn run(&self) -> Result<()> {
+        validate_task_name(&self.from)?;
+        validate_task_name(&self.to)?;
+
+        let ts = open_task_system(&self.db)?;
+
+        let from_task = open_task(&ts, &self.from)?;
+        if ts.exists(&self.to)? && !try_overwrite(&self.to) {
+            return Ok(());
+        }
+
+        let mut to_task = Task::current(&self.to);
+        to_task.copy_from(&from_task);
+
+        let mut batch = ts.batch();
+        batch.carry_revisions(from_task.path(), to_task.path(), true)?;
+        batch.carry_last_run(&from_task, &to_task)?;
+        batch.save(&to_task)?;
+        batch.remove(from_task.path())?;
+        batch.commit()?;
+        Ok(())
+    }
+}
+
+pub struct MoveDir {
+    pub db: Config,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub recursive: bool,
+    pub conflict: Conflict,
+}
+
+impl Cmd for MoveDir {
+    fn run(&self) -> Result<()> {
+        let ts = open_task_system(&self.db)?;
+        let n = transfer_dir(
+            &ts,
+            &self.from,
+            &self.to,
+            self.recursive,
+            self.conflict,
+            true,
+        )?;
+        print_done(&format!("{} task(s) are moved", n));
+        Ok(())
+    }
+}
+
+pub struct CopyDir {
+    pub db: Config,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub recursive: bool,
+    pub conflict: Conflict,
+}
+
+impl Cmd for CopyDir {
+    fn run(&self) -> Result<()> {
+        let ts = open_task_system(&self.db)?;
+        let n = transfer_dir(
+            &ts,
+            &self.from,
+            &self.to,
+            self.recursive,
+            self.conflict,
+            false,
+        )?;
+        print_done(&format!("{} task(s) are copied", n));
+        Ok(())
+    }
+}
+
+// transfer_dir copies the tasks of a directory to another one in a single batch.
+// The source tasks are removed in the same batch when remove_source is set.
+fn transfer_dir(
+    ts: &TaskSystem,
+    from: &Path,
+    to: &Path,
+    recursive: bool,
+    conflict: Conflict,
+    remove_source: bool,
+) -> Result<usize> {
+    let from = normalize_dir(from)?;
+    let to = normalize_dir(to)?;
+    if from == to {
+        return Err(Error::new(
+            "The source and the destination are the same directory",
+        ));
+    }
+    if recursive && to.starts_with(&from) {
+        return Err(Error::with_suggest(
+            format!(
+                "'{}' is under '{}'",
+                to.to_string_lossy().yellow(),
+                from.to_string_lossy().yellow()
+            ),
+            "With --recursive, the destination can't be a subdirectory of the source".to_owned(),
+        )
+        .with_code(EXIT_USAGE));
+    }
+    // The source may be gone already, but tasks moved to a missing directory would be lost track of.
+    if !to.is_dir() {
+        return Err(Error::with_suggest(
+            format!(
+                "'{}' is not an existing directory",
+                to.to_string_lossy().yellow()
+            ),
+            "Move or create the directory first".to_owned(),
+        )
+        .with_code(EXIT_NOT_FOUND));
+    }
+
+    let tasks: Vec<Task> = ts.scan(&from, !recursive)?.collect();
+    if tasks.is_empty() {
+        return Err(Error::new(format!(
+            "No task in '{}'",
+            from.to_string_lossy().yellow()
+        )));
+    }
+
+    let mut batch = ts.batch();
+    let mut count = 0;
+    for task in tasks {
+        let relative = Path::new(task.path()).strip_prefix(&from).unwrap();
+        let dest = to.join(relative);
+        if ts.exists(&dest)? && !conflict.accept(&dest.to_string_lossy()) {
+            continue;
+        }
+
+        let mut copied = Task::at(&dest);
+        copied.copy_from(&task);
+        batch.carry_revisions(task.path(), copied.path(), remove_source)?;
+        batch.carry_last_run(&task, &copied)?;
+        batch.save(&copied)?;
+        println!("  {} -> {}", task.path(), dest.to_string_lossy().green());
+        if remove_source {
+            batch.remove(task.path())?;
+        }
+        count += 1;
+    }
+    batch.commit()?;
+    Ok(count)
+}
+
+fn normalize_dir(dir: &Path) -> Result<PathBuf> {
+    normalize(dir).ok_or_else(|| {
+        Error::new(format!(
+            "'{}' is invalid path",
+            dir.to_string_lossy().yellow()
+        ))
+        .with_code(EXIT_USAGE)
+    })
+}
+
+// How deep doctor looks for a relocated directory under the search root.
+const SEARCH_DEPTH: usize = 5;
+
+pub struct Doctor {
+    pub db: Config,
+    pub root: PathBuf,
+    pub check: bool,
+}
+
+impl Cmd for Doctor {
+    fn run(&self) -> Result<()> {
+        let ts = open_task_system(&self.db)?;
+
+        let mut broken = Vec::new();
+        let mut orphans: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+        for (path, task) in ts.records()? {
+            match task {
+                Ok(task) => {
+                    let dir = path.parent().unwrap_or_else(|| Path::new("/"));
+                    if !dir.is_dir() {
+                        orphans
+                            .entry(dir.to_path_buf())
+                            .or_default()
+                            .push(task.name().to_owned());
+                    }
+                }
+                Err(_) => broken.push(path),
+            }
+        }
+
+        if broken.is_empty() && orphans.is_empty() {
+            print_done("No problem is found");
+            return Ok(());
+        }
+        let problems = broken.len() + orphans.len();
+
+        for path in broken {
+            println!("{} {}", "broken:".red().bold(), path.to_string_lossy());
+            if !self.check && prompt("  Delete it? [y/n]: ").starts_with('y') {
+                ts.remove_record(&path)?;
+            }
+        }
+
+        for (dir, names) in orphans {
+            println!(
+                "{} {} ({})",
+                "missing:".yellow().bold(),
+                dir.to_string_lossy(),
+                names.join(", ")
+            );
+            if self.check {
+                continue;
+            }
+
+            let answer = prompt("  [d]elete, [r]elocate or [s]kip? ");
+            if answer.starts_with('d') {
+                let mut batch = ts.batch();
+                for name in &names {
+                    batch.remove(dir.join(name))?;
+                }
+                batch.commit()?;
+            } else if answer.starts_with('r') {
+                if let Some(dest) = self.choose_destination(&dir) {
+                    transfer_dir(&ts, &dir, &dest, false, Conflict::Prompt, true)?;
+                }
+            }
+        }
+
+        // A check fails when it finds any problem, so that scripts can tell.
+        if self.check {
+            return Err(Error::new(format!("{} problem(s) are found", problems)));
+        }
+        Ok(())
+    }
+}
+
+impl Doctor {
+    fn choose_destination(&self, missing: &Path) -> Option<PathBuf> {
+        let candidates = search::candidates(&self.root, missing, SEARCH_DEPTH);
+        for (i, candidate) in candidates.iter().enumerate() {
+            println!("  {}) {}", i + 1, candidate.to_string_lossy().green());
+        }
+
+        let answer = if candidates.is_empty() {
+            prompt("  No similar directory is found. Enter a directory (empty to skip): ")
+        } else {
+            prompt("  Enter a number or a directory (empty to skip): ")
+        };
+        if answer.is_empty() {
+            return None;
+        }
+
+        let dest = match answer.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= candidates.len() => candidates[n - 1].clone(),
+            _ => normalize(&answer)?,
+        };
+        if dest.is_dir() {
+            Some(dest)
+        } else {
+            println!("  '{}' is not a directory", dest.to_string_lossy().yellow());
+            None
+        }
+    }
+}
+
+pub struct Export {
+    pub db: Config,
+    pub dir: PathBuf,
+    pub recursive: bool,
+    pub output: Option<PathBuf>,
+}
+
+impl Cmd for Export {
+    fn run(&self) -> Result<()> {
+        let ts = open_task_system(&self.db)?;
+
+        let mut bundle = Bundle::new();
+        for task in ts.scan(&self.dir, !self.recursive)? {
+            bundle.push(&self.dir, &task)?;
+        }
+        if bundle.tasks.is_empty() {
+            return Err(Error::new(format!(
+                "No task in '{}'",
+                self.dir.to_string_lossy().yellow()
+            )));
+        }
+
+        match self.output {
+            Some(ref output) => {
+                File::create(output)?.write_all(&bundle.to_vec())?;
+                print_done(&format!("{} task(s) are exported", bundle.tasks.len()));
+            }
+            None => {
+                stdout().write_all(&bundle.to_vec())?;
+                println!();
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Import {
+    pub db: Config,
+    pub file: PathBuf,
+    pub into: PathBuf,
+    pub conflict: Conflict,
+}
+
+impl Cmd for Import {
+    fn run(&self) -> Result<()> {
+        let mut content = Vec::new();
+        File::open(&self.file)?.read_to_end(&mut content)?;
+        let bundle = Bundle::from_slice(&content)?;
+
+        let into = normalize_dir(&self.into)?;
+        let ts = open_task_system(&self.db)?;
+
+        // Validate every entry first so that a broken bundle imports nothing.
+        let mut tasks = Vec::new();
+        for entry in &bundle.tasks {
+            tasks.push(entry.to_task(&into)?);
+        }
+
+        let mut batch = ts.batch();
+        let mut count = 0;
+        for task in tasks {
+            if ts.exists(task.path())? && !self.conflict.accept(task.path()) {
+                continue;
+            }
+            batch.save(&task)?;
+            count += 1;
+        }
+        batch.commit()?;
+
+        print_done(&format!("{} task(s) are imported", count));
+        Ok(())
+    }
+}
+
+pub struct Log {
+    pub db: Config,
+    pub task: String,
+}
+
+impl Cmd for Log {
+    fn run(&self) -> Result<()> {
+        validate_task_name(&self.task)?;
+        let ts = open_task_system(&self.db)?;
+
+        let revisions = ts.revisions(&self.task)?;
+        if revisions.is_empty() {
+            return Err(TaskError::NotFound(self.task.clone()).into());
+        }
+
+        for rev in revisions.iter().rev() {
+            println!(
+                "{} {}  {}  {}",
+                format!("rev {}", rev.number()).yellow().bold(),
+                time::format(rev.timestamp()),
+                rev.author().green(),
+                &rev.hash()[..8]
+            );
+            println!(
+                "    inherit: {}, extension: {}, {} bytes",
+                rev.inherit(),
+                rev.extension(),
+                rev.content().len()
+            );
+        }
+        Ok(())
+    }
+}
+
+pub struct Diff {
+    pub db: Config,
+    pub task: String,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+impl Cmd for Diff {
+    fn run(&self) -> Result<()> {
+        validate_task_name(&self.task)?;
+        let ts = open_task_system(&self.db)?;
+
+        // Without revisions, compare the last two. With one, compare it with the current task.
+        let (old, new) = match (self.from, self.to) {
+            (Some(from), Some(to)) => (
+                ts.revision(&self.task, from)?.content().to_vec(),
+                ts.revision(&self.task, to)?.content().to_vec(),
+            ),
+            (Some(from), None) => (
+                ts.revision(&self.task, from)?.content().to_vec(),
+                ts.open(&self.task)?.take(),
+            ),
+            _ => {
+                let revisions = ts.revisions(&self.task)?;
+                match revisions.len() {
+                    0 => return Err(TaskError::NotFound(self.task.clone()).into()),
+                    1 => (Vec::new(), revisions[0].content().to_vec()),
+                    n => (
+                        revisions[n - 2].content().to_vec(),
+                        revisions[n - 1].content().to_vec(),
+                    ),
+                }
+            }
+        };
+
+        let old = String::from_utf8_lossy(&old);
+        let new = String::from_utf8_lossy(&new);
+        for line in diff::lines(&old, &new) {
+            match line {
+                diff::Line::Same(s) => println!(" {}", s),
+                diff::Line::Removed(s) => println!("{}", format!("-{}", s).red()),
+                diff::Line::Added(s) => println!("{}", format!("+{}", s).green()),
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Revert {
+    pub db: Config,
+    pub task: String,
+    pub revision: u64,
+}
+
+impl Cmd for Revert {
+    fn run(&self) -> Result<()> {
+        validate_task_name(&self.task)?;
+        let ts = open_task_system(&self.db)?;
+
+        let revision = ts.revision(&self.task, self.revision)?;
+        let mut task = match ts.open(&self.task) {
+            Ok(task) => task,
+            Err(TaskError::NotFound(_)) => Task::current(&self.task),
+            Err(e) => return Err(e.into()),
+        };
+        revision.restore(&mut task);
+        ts.save(&task)?;
+
+        print_done(&format!(
+            "The task is reverted to revision {}",
+            self.revision
+        ));
+        Ok(())
+    }
+}
+
+pub struct Migrate {
+    pub db: Config,
+    pub dry_run: bool,
+}
+
+impl Cmd for Migrate {
+    fn run(&self) -> Result<()> {
+        let db = if self.dry_run {
+            match open_existing_store(&self.db)? {
+                Some(db) => db,
+                None => {
+                    print_done(&format!(
+                        "There is no database at '{}' yet. It will be created at version {} when it is first used",
+                        self.db.path.to_string_lossy(),
+                        schema::VERSION
+                    ));
+                    return Ok(());
+                }
+            }
+        } else {
+            open_store(&self.db)?
+        };
+        let report = schema::migrate(&*db, self.dry_run)?;
+
+        for task in &report.broken {
+            println!("{} {}", "broken:".red().bold(), task);
+        }
+
+        if report.is_up_to_date() {
+            print_done(&format!(
+                "The database is up to date (version {})",
+                report.to
+            ));
+            return Ok(());
+        }
+
+        for step in &report.steps {
+            println!(
+                "{} {}",
+                format!("[v{}]", step.version).green().bold(),
+                step.description
+            );
+            for task in &step.changed {
+                println!("  {}", task);
+            }
+        }
+
+        if self.dry_run {
+            println!(
+                "Version {} would be migrated to {}. Run it again without --dry-run to apply.",
+                report.from, report.to
+            );
+        } else {
+            print_done(&format!(
+                "The database is migrated from version {} to {}",
+                report.from, report.to
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn create_tempfile(ext: &str) -> Result<TempFile> {
+    TempFile::create(tempfile::random_name(ext))
+}
+
+// command_line describes how the task is executed, like 'python3 <script> [args]'.
+fn command_line(task: &Task, args: &[String]) -> String {
+    let mut words = exec::interpreter(task);
+    words.push("<script>".to_owned());
+    words.extend(args.iter().cloned());
+    if args.is_empty() {
+        words.push("[args]".to_owned());
+    }
+
+    let mut line = words.join(" ");
+    if let Some(shebang) = exec::shebang(task.content()) {
+        if task.interpreter().is_empty() {
+            line.push_str(&format!(" (#!{})", shebang));
+        }
+    }
+    line
+}
+
+// exit_result turns the exit status of a task into the result of chore.
+// A task killed by a signal is reported as 128 + signal like shells do.
+fn exit_result(status: ExitStatus) -> Result<()> {
+    match exit_code(status) {
+        0 => Ok(()),
+        code => Err(Error::exited(code)),
+    }
+}
+
+// task_result tells how the task ended, which is an error when it failed or timed out.
+fn task_result(name: &str, exit: &signal::Exit, timeout: Option<Duration>) -> Result<()> {
+    if !exit.timed_out {
+        return exit_result(exit.status);
+    }
+    Err(Error::with_suggest(
+        format!(
+            "Task '{}' timed out after {}",
+            name,
+            time::format_seconds(timeout.map(|t| t.as_secs()).unwrap_or(0))
+        ),
+        "Give it more time with --timeout or 'chore set <task> timeout=...'".to_owned(),
+    )
+    .with_code(EXIT_TIMEOUT))
+}
+
+// exit_code is the code of the task as a shell reports it, 128 + the signal when it was killed.
+fn exit_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    if status.success() {
+        return 0;
+    }
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => EXIT_FAILURE,
+    }
+}
+
+// format_time prints a timestamp of a task, which is zero when unknown or never happened.
+fn format_time(secs: u64) -> String {
+    if secs == 0 {
+        "-".to_owned()
+    } else {
+        time::format(secs)
+    }
+}
+
+fn print_done(message: &str) {
+    println!("    {} {}", "Done".green().bold(), message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use super::{
+        open_task_system, resolve_task, transfer_dir, Cmd, Conflict, Doctor, OnChange, Run,
+    };
+    use crate::db::{Backend, Config};
+    use crate::error::{EXIT_FAILURE, EXIT_NOT_FOUND, EXIT_USAGE};
+    use crate::task::{Alias, Task};
+
+    // setup makes a directory with src and dst in it, and a database with the given tasks.
+    fn setup(name: &str, tasks: &[&str]) -> (PathBuf, Config) {
+        let root = env::temp_dir().join(format!("chore-command-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(root.join("dst")).unwrap();
+        let db = Config {
+            backend: Backend::Fs,
+            path: root.join("db"),
+            revisions: 0,
+            history: 0,
+            logs: root.join("logs"),
+        };
+
+        let ts = open_task_system(&db).unwrap();
+        let mut batch = ts.batch();
+        for path in tasks {
+            let mut task = Task::at(root.join(path));
+            task.set_content(b"echo".to_vec());
+            batch.save(&task).unwrap();
+        }
+        batch.commit().unwrap();
+        (root, db)
+    }
+
+    #[test]
+    fn runs_are_recorded_after_the_task_exits() {
+        let (root, db) = setup("run", &["src/a"]);
+        let run = Run {
+            db: db.clone(),
+            dir: root.join("src"),
+            tasks: vec!["a".to_owned()],
+            args: Vec::new(),
+            env: Vec::new(),
+            dotenv: false,
+            print_env: false,
+            keep_going: false,
+            parallel: false,
+            jobs: 0,
+            log: false,
+            timeout: None,
+            watch: None,
+            on_change: OnChange::Restart,
+        };
+        run.run().unwrap();
+
+        let ts = open_task_system(&db).unwrap();
+        let history = ts.history().unwrap();
+        let task = resolve_task(&ts, &root.join("src"), "a").unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].exit_code, 0);
+        assert_eq!(ts.last_run_at(&task).unwrap(), history[0].started_at);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn moves_the_tasks_of_a_directory() {
+        let (root, db) = setup("mv-dir", &["src/a", "src/b", "src/sub/c"]);
+        let (src, dst) = (root.join("src"), root.join("dst"));
+        let ts = open_task_system(&db).unwrap();
+        let n = transfer_dir(&ts, &src, &dst, false, Conflict::Skip, true).unwrap();
+        assert_eq!(n, 2);
+        assert!(ts.exists(dst.join("a")).unwrap());
+        assert!(!ts.exists(src.join("a")).unwrap());
+        assert!(ts.exists(src.join("sub/c")).unwrap());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn recursive_destination_under_the_source_is_rejected() {
+        let (root, db) = setup("nested", &["src/a"]);
+        let src = root.join("src");
+        let ts = open_task_system(&db).unwrap();
+        assert!(transfer_dir(&ts, &src, &src.join("sub"), true, Conflict::Skip, false).is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn missing_destination_is_rejected() {
+        let (root, db) = setup("missing", &["src/a"]);
+        let src = root.join("src");
+        let ts = open_task_system(&db).unwrap();
+        let err = transfer_dir(&ts, &src, &root.join("typo"), false, Conflict::Skip, true)
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), EXIT_NOT_FOUND);
+        assert!(ts.exists(src.join("a")).unwrap());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn doctor_check_fails_on_a_missing_directory() {
+        let (root, db) = setup("doctor", &["src/a"]);
+        let doctor = |db| Doctor {
+            db,
+            root: root.clone(),
+            check: true,
+        };
+        assert!(doctor(db.clone()).run().is_ok());
+
+        fs::remove_dir_all(root.join("src")).unwrap();
+        let err = doctor(db).run().err().unwrap();
+        assert_eq!(err.code(), EXIT_FAILURE);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn names_resolve_by_alias_or_unique_prefix() {
+        let (root, db) = setup("resolve", &["src/build", "src/bench", "src/test"]);
+        let src = root.join("src");
+        let ts = open_task_system(&db).unwrap();
+        ts.save_alias(&Alias::new(src.join("t"), "test", false))
+            .unwrap();
+
+        let name = |name: &str| resolve_task(&ts, &src, name).map(|t| t.name().to_owned());
+        assert_eq!(name("t").unwrap(), "test");
+        assert_eq!(name("bu").unwrap(), "build");
+        assert_eq!(name("b").err().unwrap().code(), EXIT_USAGE);
+        assert!(name("deploy").is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
 }
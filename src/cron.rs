@@ -0,0 +1,281 @@
+use std::result;
+
+use crate::time::{self, Local};
+
+// Shorthands for common schedules.
+const MACROS: &[(&str, &str)] = &[
+    ("@yearly", "0 0 1 1 *"),
+    ("@annually", "0 0 1 1 *"),
+    ("@monthly", "0 0 1 * *"),
+    ("@weekly", "0 0 * * 0"),
+    ("@daily", "0 0 * * *"),
+    ("@midnight", "0 0 * * *"),
+    ("@hourly", "0 * * * *"),
+];
+
+const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: &[&str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// How far next_after looks ahead. Eight years cover a February 29 after a century
+// which isn't a leap year.
+const LOOKAHEAD_DAYS: i64 = 8 * 366;
+
+// Schedule is a cron expression of five fields, minute hour day month weekday, in local time.
+// Every field is a set of values, kept as bits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Whether the day or the weekday is restricted. When both are, either of them matches like cron.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    // parse reads an expression like '0 3 * * *', '*/15 9-17 * * mon-fri' or '@daily'.
+    pub fn parse(expression: &str) -> result::Result<Schedule, String> {
+        let expression = expression.trim();
+        let expression = MACROS
+            .iter()
+            .find(|&&(name, _)| name == expression)
+            .map(|&(_, expanded)| expanded)
+            .unwrap_or(expression);
+
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!(
+                "'{}' should have five fields: minute hour day month weekday",
+                expression
+            ));
+        }
+        let weekdays = parse_field(fields[4], 0, 7, WEEKDAYS)?;
+        Ok(Schedule {
+            minutes: parse_field(fields[0], 0, 59, &[])?,
+            hours: parse_field(fields[1], 0, 23, &[])?,
+            days: parse_field(fields[2], 1, 31, &[])?,
+            months: parse_field(fields[3], 1, 12, MONTHS)?,
+            // 7 is Sunday as well as 0.
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+
+    // next_after returns when it fires first after the unix timestamp,
+    // or None when it never does, like on February 30.
+    pub fn next_after(&self, secs: u64) -> Option<u64> {
+        let start = secs / 60 * 60 + 60;
+        let from = Local::at(start)?;
+        let first_day = time::days_from_civil(from.year, from.month, from.day);
+
+        for days in first_day..first_day + LOOKAHEAD_DAYS {
+            let (year, month, day) = time::civil_from_days(days);
+            // 1970-01-01 was a Thursday.
+            let weekday = (days + 4).rem_euclid(7) as u32;
+            if !self.matches_day(month, day, weekday) {
+                continue;
+            }
+            for hour in (0..24).filter(|&h| has(self.hours, h)) {
+                for minute in (0..60).filter(|&m| has(self.minutes, m)) {
+                    let at = Local {
+                        year,
+                        month,
+                        day,
+                        hour,
+                        minute,
+                    };
+                    if days == first_day && at < from {
+                        continue;
+                    }
+                    match at.timestamp() {
+                        Some(t) if t >= start => return Some(t),
+                        _ => (),
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, month: u32, day: u32, weekday: u32) -> bool {
+        if !has(self.months, month) {
+            return false;
+        }
+        let day = has(self.days, day);
+        let weekday = has(self.weekdays, weekday);
+        if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+}
+
+fn has(bits: u64, value: u32) -> bool {
+    bits & 1 << value != 0
+}
+
+// parse_field reads a comma separated list of '*', values and ranges, each with an optional step
+// like '*/15' or '1-5/2'. Values may be given by names, which start from min.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> result::Result<u64, String> {
+    let value = |s: &str| -> result::Result<u32, String> {
+        let s = s.to_lowercase();
+        let n = match names.iter().position(|&name| name == s) {
+            Some(i) => i as u32 + min,
+            None => s
+                .parse()
+                .map_err(|_| format!("'{}' is not a number in '{}'", s, field))?,
+        };
+        if n < min || n > max {
+            return Err(format!("{} is out of {}-{} in '{}'", n, min, max, field));
+        }
+        Ok(n)
+    };
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let mut range_step = part.splitn(2, '/');
+        let range = range_step.next().unwrap_or_default();
+        let step = match range_step.next() {
+            Some(step) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => Some(step),
+                _ => return Err(format!("'{}' is not a step in '{}'", step, field)),
+            },
+            None => None,
+        };
+
+        let (low, high) = if range == "*" {
+            (min, max)
+        } else if let Some(dash) = range.find('-') {
+            (value(&range[..dash])?, value(&range[dash + 1..])?)
+        } else {
+            let n = value(range)?;
+            // Like cron, '5/10' means from 5 to the end every 10.
+            (n, if step.is_some() { max } else { n })
+        };
+        if low > high {
+            return Err(format!("{}-{} is an empty range in '{}'", low, high, field));
+        }
+        for n in (low..=high).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_field, Schedule};
+    use crate::time::Local;
+
+    fn at(year: i64, month: u32, day: u32, hour: u32, minute: u32) -> u64 {
+        Local {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+        }
+        .timestamp()
+        .unwrap()
+    }
+
+    fn next(expression: &str, after: u64) -> Option<u64> {
+        Schedule::parse(expression).unwrap().next_after(after)
+    }
+
+    #[test]
+    fn macros_are_expanded() {
+        assert_eq!(Schedule::parse("@daily"), Schedule::parse("0 0 * * *"));
+        assert_eq!(Schedule::parse(" @hourly "), Schedule::parse("0 * * * *"));
+        assert!(Schedule::parse("@sometimes").is_err());
+    }
+
+    #[test]
+    fn names_are_numbers() {
+        assert_eq!(
+            Schedule::parse("0 0 * jan-mar mon-fri"),
+            Schedule::parse("0 0 * 1-3 1-5")
+        );
+        assert_eq!(Schedule::parse("0 0 * * SUN"), Schedule::parse("0 0 * * 0"));
+    }
+
+    #[test]
+    fn sunday_is_also_7() {
+        assert_eq!(Schedule::parse("0 0 * * 7"), Schedule::parse("0 0 * * 0"));
+        assert_eq!(
+            Schedule::parse("0 0 * * 5-7"),
+            Schedule::parse("0 0 * * 0,5,6")
+        );
+    }
+
+    #[test]
+    fn ranges_and_steps() {
+        let bits = |values: &[u32]| values.iter().fold(0, |b, v| b | 1 << v);
+        assert_eq!(parse_field("*/15", 0, 59, &[]), Ok(bits(&[0, 15, 30, 45])));
+        assert_eq!(parse_field("1-5/2", 0, 59, &[]), Ok(bits(&[1, 3, 5])));
+        assert_eq!(parse_field("50/5", 0, 59, &[]), Ok(bits(&[50, 55])));
+        assert_eq!(parse_field("1,3-4", 0, 59, &[]), Ok(bits(&[1, 3, 4])));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        for expression in &[
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "5-1 * * * *",
+            "*/0 * * * *",
+            "* * * foo *",
+        ] {
+            assert!(Schedule::parse(expression).is_err(), "{}", expression);
+        }
+    }
+
+    #[test]
+    fn next_is_strictly_after() {
+        // 2024-01-15 is a Monday.
+        let schedule = "*/15 9-17 * * mon-fri";
+        assert_eq!(
+            next(schedule, at(2024, 1, 15, 10, 7)),
+            Some(at(2024, 1, 15, 10, 15))
+        );
+        assert_eq!(
+            next(schedule, at(2024, 1, 15, 10, 15)),
+            Some(at(2024, 1, 15, 10, 30))
+        );
+        assert_eq!(
+            next(schedule, at(2024, 1, 15, 17, 45)),
+            Some(at(2024, 1, 16, 9, 0))
+        );
+        assert_eq!(
+            next(schedule, at(2024, 1, 19, 18, 0)),
+            Some(at(2024, 1, 22, 9, 0))
+        );
+    }
+
+    #[test]
+    fn day_or_weekday_when_both_are_restricted() {
+        // 2024-01-05 is a Friday.
+        let after = at(2024, 1, 1, 12, 0);
+        assert_eq!(next("0 0 13 * fri", after), Some(at(2024, 1, 5, 0, 0)));
+        assert_eq!(next("0 0 13 * *", after), Some(at(2024, 1, 13, 0, 0)));
+        assert_eq!(next("0 0 * * fri", after), Some(at(2024, 1, 5, 0, 0)));
+    }
+
+    #[test]
+    fn impossible_dates_never_fire() {
+        assert_eq!(next("0 0 30 2 *", at(2024, 1, 1, 0, 0)), None);
+        assert_eq!(
+            next("0 0 29 2 *", at(2024, 3, 1, 0, 0)),
+            Some(at(2028, 2, 29, 0, 0))
+        );
+    }
+}
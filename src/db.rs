@@ -1,10 +1,249 @@
-use rocksdb::{Options, DB};
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-use crate::error::Result;
+use crate::error::{Error, Result, EXIT_CANT_CREATE, EXIT_USAGE};
+use crate::schema;
+use crate::store::{FsStore, MemoryStore, TaskStore};
 
-pub fn open_database() -> Result<DB> {
-    let mut db_opts = Options::default();
-    db_opts.create_if_missing(true);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    RocksDB,
+    Fs,
+    Memory,
+}
+
+impl Backend {
+    fn from_name(name: &str) -> Result<Backend> {
+        match name {
+            "rocksdb" => Ok(Backend::RocksDB),
+            "fs" => Ok(Backend::Fs),
+            "memory" => Ok(Backend::Memory),
+            _ => Err(Error::with_suggest(
+                format!("'{}' is not a storage backend", name),
+                "Available backends are rocksdb, fs and memory".to_owned(),
+            )
+            .with_code(EXIT_USAGE)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::RocksDB => "rocksdb",
+            Backend::Fs => "fs",
+            Backend::Memory => "memory",
+        }
+    }
+}
+
+impl Default for Backend {
+    #[cfg(feature = "rocksdb")]
+    fn default() -> Backend {
+        Backend::RocksDB
+    }
+
+    #[cfg(not(feature = "rocksdb"))]
+    fn default() -> Backend {
+        Backend::Fs
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub backend: Backend,
+    pub path: PathBuf,
+    pub revisions: usize,
+    pub history: usize,
+    // Where the logs of 'chore run --log' are written.
+    pub logs: PathBuf,
+}
+
+// The number of revisions kept for each task unless CHORE_REVISIONS is set.
+const DEFAULT_REVISIONS: usize = 20;
+// The number of runs kept in the history unless CHORE_HISTORY is set.
+const DEFAULT_HISTORY: usize = 1000;
+
+impl Config {
+    // resolve builds a config from the command line options and the environment.
+    // The backend is chosen by --backend, then CHORE_BACKEND, then the build default.
+    pub fn resolve(path: Option<&str>, backend: Option<&str>) -> Result<Config> {
+        let backend = match backend {
+            Some(name) => Backend::from_name(name)?,
+            None => match non_empty_var("CHORE_BACKEND") {
+                Some(name) => Backend::from_name(&name.to_string_lossy())?,
+                None => Backend::default(),
+            },
+        };
+
+        let revisions = limit_var("CHORE_REVISIONS", DEFAULT_REVISIONS, "revision")?;
+        let history = limit_var("CHORE_HISTORY", DEFAULT_HISTORY, "run")?;
+
+        let path = location(path)?;
+        let logs = match non_empty_var("CHORE_LOG_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => log_location(&path),
+        };
+
+        Ok(Config {
+            backend,
+            path,
+            revisions,
+            history,
+            logs,
+        })
+    }
+}
+
+// limit_var reads a number of things to keep from the environment, where 0 means no limit.
+fn limit_var(name: &str, default: usize, thing: &str) -> Result<usize> {
+    match non_empty_var(name) {
+        Some(n) => n.to_string_lossy().parse().map_err(|_| {
+            Error::with_suggest(
+                format!("{} must be a number, not '{}'", name, n.to_string_lossy()),
+                format!("Set it to 0 to keep every {}", thing),
+            )
+            .with_code(EXIT_USAGE)
+        }),
+        None => Ok(default),
+    }
+}
+
+// location resolves where the task database lives.
+// An explicit path (--db) wins over CHORE_DB, which wins over the XDG default.
+fn location(path: Option<&str>) -> Result<PathBuf> {
+    if let Some(path) = path {
+        return Ok(PathBuf::from(path));
+    }
+
+    if let Some(path) = non_empty_var("CHORE_DB") {
+        return Ok(PathBuf::from(path));
+    }
+
+    let mut data_home = match non_empty_var("XDG_DATA_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => match non_empty_var("HOME") {
+            Some(home) => {
+                let mut dir = PathBuf::from(home);
+                dir.push(".local");
+                dir.push("share");
+                dir
+            }
+            None => {
+                return Err(Error::with_suggest(
+                    "Unable to determine where to store tasks",
+                    "Set CHORE_DB or pass --db with the path of the database",
+                ))
+            }
+        },
+    };
+    data_home.push("chore");
+    Ok(data_home)
+}
+
+// log_location is next to the database, like ~/.local/share/chore-logs.
+// It can't be in the database directory, which belongs to the storage backend.
+fn log_location(db: &Path) -> PathBuf {
+    let mut name = db.file_name().unwrap_or_default().to_os_string();
+    name.push("-logs");
+    db.with_file_name(name)
+}
+
+// open_database opens the store and upgrades it to the current schema.
+pub fn open_database(config: &Config) -> Result<Box<dyn TaskStore>> {
+    let store = open_store(config)?;
+    schema::upgrade(&*store)?;
+    Ok(store)
+}
+
+// open_existing_store opens the store as it is, like open_store, but only when there is one.
+// Nothing is created for a database which doesn't exist yet.
+pub fn open_existing_store(config: &Config) -> Result<Option<Box<dyn TaskStore>>> {
+    let path = config.path.as_path();
+    if config.backend != Backend::Memory && !path.exists() {
+        return Ok(None);
+    }
+    open_store(config).map(Some)
+}
+
+// open_store opens the store as it is, without any migration.
+pub fn open_store(config: &Config) -> Result<Box<dyn TaskStore>> {
+    let path = config.path.as_path();
+    if config.backend != Backend::Memory {
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                return Err(unwritable(path, &e.to_string()));
+            }
+        }
+    }
+
+    match config.backend {
+        Backend::RocksDB => open_rocksdb(path),
+        Backend::Fs => match FsStore::open(path) {
+            Ok(store) => Ok(Box::new(store)),
+            Err(e) => Err(unwritable(path, &e.to_string())),
+        },
+        Backend::Memory => Ok(Box::new(MemoryStore::new())),
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+fn open_rocksdb(path: &Path) -> Result<Box<dyn TaskStore>> {
+    use crate::store::RocksStore;
+    match RocksStore::open(path) {
+        Ok(store) => Ok(Box::new(store)),
+        Err(e) => Err(unwritable(path, &e.to_string())),
+    }
+}
+
+#[cfg(not(feature = "rocksdb"))]
+fn open_rocksdb(_: &Path) -> Result<Box<dyn TaskStore>> {
+    Err(Error::with_suggest(
+        "chore is built without RocksDB support",
+        "Rebuild it with '--features rocksdb' or use another backend",
+    )
+    .with_code(EXIT_USAGE))
+}
+
+fn non_empty_var(key: &str) -> Option<OsString> {
+    env::var_os(key).and_then(|v| if v.is_empty() { None } else { Some(v) })
+}
+
+fn unwritable(path: &Path, reason: &str) -> Error {
+    use colored::*;
+    Error::with_suggest(
+        format!(
+            "Unable to open the database at '{}': {}",
+            path.to_string_lossy().yellow(),
+            reason
+        ),
+        format!(
+            "Set {} or pass {} with a writable location",
+            "CHORE_DB".green(),
+            "--db".green()
+        ),
+    )
+    .with_code(EXIT_CANT_CREATE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::{open_existing_store, Backend, Config};
 
-    DB::open(&db_opts, "/Users/phynalle/.tmp/testdb").map_err(|e| e.into())
+    #[test]
+    fn missing_database_is_not_created() {
+        let path = env::temp_dir().join(format!("chore-db-{}-missing", process::id()));
+        let config = Config {
+            backend: Backend::Fs,
+            path: path.clone(),
+            revisions: 0,
+            history: 0,
+            logs: path.join("logs"),
+        };
+        assert!(open_existing_store(&config).unwrap().is_none());
+        assert!(!path.exists());
+    }
 }
@@ -0,0 +1,89 @@
+pub enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// lines computes a line-based diff from old to new with the longest common subsequence.
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let (n, m) = (old.len(), new.len());
+
+    // lcs[i][j] is the length of the common subsequence of old[i..] and new[j..].
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            result.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            result.push(Line::Removed(old[i]));
+            i += 1;
+        } else {
+            result.push(Line::Added(new[j]));
+            j += 1;
+        }
+    }
+    result.extend(old[i..].iter().map(|s| Line::Removed(s)));
+    result.extend(new[j..].iter().map(|s| Line::Added(s)));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lines, Line};
+
+    fn render(old: &str, new: &str) -> Vec<String> {
+        lines(old, new)
+            .iter()
+            .map(|line| match *line {
+                Line::Same(s) => format!(" {}", s),
+                Line::Removed(s) => format!("-{}", s),
+                Line::Added(s) => format!("+{}", s),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn same_content_has_no_change() {
+        assert_eq!(render("a\nb\n", "a\nb\n"), vec![" a", " b"]);
+        assert!(render("", "").is_empty());
+    }
+
+    #[test]
+    fn lines_are_added_and_removed() {
+        assert_eq!(render("", "a\nb"), vec!["+a", "+b"]);
+        assert_eq!(render("a\nb", ""), vec!["-a", "-b"]);
+        assert_eq!(render("a\nc", "a\nb\nc"), vec![" a", "+b", " c"]);
+        assert_eq!(render("a\nb\nc", "a\nc"), vec![" a", "-b", " c"]);
+    }
+
+    #[test]
+    fn changed_line_is_removed_then_added() {
+        assert_eq!(
+            render("set -e\necho 1\nexit 0", "set -e\necho 2\nexit 0"),
+            vec![" set -e", "-echo 1", "+echo 2", " exit 0"]
+        );
+    }
+
+    #[test]
+    fn longest_common_lines_are_kept() {
+        assert_eq!(
+            render("a\nb\nc\nd", "b\nc\nd\na"),
+            vec!["-a", " b", " c", " d", "+a"]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{Error, Result, EXIT_BROKEN_DATA, EXIT_USAGE};
+use crate::params::Param;
+use crate::task::Task;
+
+// DOTENV_FILE is a file of KEY=VALUE lines, usually kept out of the repository.
+pub const DOTENV_FILE: &str = ".env";
+
+// Origin tells where a variable of the environment came from.
+pub enum Origin {
+    Inherited,
+    Chore,
+    Task,
+    File(PathBuf),
+    Override,
+    Argument,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Origin::Inherited => write!(f, "inherited"),
+            Origin::Chore => write!(f, "chore"),
+            Origin::Task => write!(f, "task"),
+            Origin::File(ref path) => write!(f, "{}", path.to_string_lossy()),
+            Origin::Override => write!(f, "--env"),
+            Origin::Argument => write!(f, "argument"),
+        }
+    }
+}
+
+// Environment is the environment a task runs with. Later layers win:
+// the environment of chore, the variables chore sets for the run, the task,
+// .env files from the root down to the run directory, --env overrides and the arguments.
+pub struct Environment {
+    vars: Vec<(String, String, Origin)>,
+}
+
+impl Environment {
+    pub fn inherited() -> Environment {
+        Environment {
+            vars: env::vars()
+                .map(|(k, v)| (k, v, Origin::Inherited))
+                .collect(),
+        }
+    }
+
+    // set replaces the value in place, so the order of the first definition is kept.
+    pub fn set(&mut self, key: &str, value: &str, origin: Origin) {
+        match self.vars.iter_mut().find(|v| v.0 == key) {
+            Some(var) => {
+                var.1 = value.to_owned();
+                var.2 = origin;
+            }
+            None => self.vars.push((key.to_owned(), value.to_owned(), origin)),
+        }
+    }
+
+    // extend_run tells the task about itself and where it was invoked.
+    pub fn extend_run(&mut self, task: &Task, invoke_dir: &Path) {
+        let task_dir = task.dir().to_string_lossy().into_owned();
+        let invoke_dir = invoke_dir.to_string_lossy().into_owned();
+        self.set("CHORE_TASK_NAME", task.name(), Origin::Chore);
+        self.set("CHORE_TASK_DIR", &task_dir, Origin::Chore);
+        self.set("CHORE_INVOKE_DIR", &invoke_dir, Origin::Chore);
+    }
+
+    pub fn extend_task(&mut self, task: &Task) {
+        for (key, value) in task.env() {
+            self.set(key, value, Origin::Task);
+        }
+    }
+
+    // load_dotenv reads the .env files of dir and its ancestors. The nearest one wins.
+    pub fn load_dotenv(&mut self, dir: &Path) -> Result<()> {
+        let mut files = Vec::new();
+        let mut dir = dir.to_path_buf();
+        loop {
+            let path = dir.join(DOTENV_FILE);
+            if path.is_file() {
+                files.push(path);
+            }
+            if !dir.pop() {
+                break;
+            }
+        }
+
+        for path in files.into_iter().rev() {
+            for (key, value) in read_dotenv(&path)? {
+                self.set(&key, &value, Origin::File(path.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    // extend_overrides applies KEY=VALUE pairs given on the command line.
+    pub fn extend_overrides(&mut self, pairs: &[String]) -> Result<()> {
+        for pair in pairs {
+            let (key, value) = parse_pair(pair).ok_or_else(|| {
+                Error::with_suggest(
+                    format!("'{}' is not a KEY=VALUE pair", pair),
+                    "For example, chore run --env RUST_LOG=debug build".to_owned(),
+                )
+                .with_code(EXIT_USAGE)
+            })?;
+            self.set(&key, &value, Origin::Override);
+        }
+        Ok(())
+    }
+
+    // extend_arguments sets the values of the parameters as CHORE_ARG_<NAME>.
+    // A parameter without a value is left unset.
+    pub fn extend_arguments(&mut self, values: &[(Param, Option<String>)]) {
+        for (param, value) in values {
+            if let Some(ref value) = *value {
+                self.set(&param.env_name(), value, Origin::Argument);
+            }
+        }
+    }
+
+    pub fn vars(&self) -> &[(String, String, Origin)] {
+        &self.vars
+    }
+
+    // apply sets the variables which chore adds on top of its own environment.
+    pub fn apply(&self, cmd: &mut Command) {
+        for (key, value, origin) in &self.vars {
+            match *origin {
+                Origin::Inherited => (),
+                _ => {
+                    cmd.env(key, value);
+                }
+            }
+        }
+    }
+}
+
+// parse_pair splits KEY=VALUE. The key must be a valid name of a variable.
+pub fn parse_pair(pair: &str) -> Option<(String, String)> {
+    let mut kv = pair.splitn(2, '=');
+    let key = kv.next()?.trim();
+    let value = kv.next()?;
+    if !is_name(key) {
+        return None;
+    }
+    Some((key.to_owned(), value.to_owned()))
+}
+
+fn is_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c == '_' || c.is_ascii_alphabetic() => (),
+        _ => return false,
+    }
+    chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+// read_dotenv parses lines like 'KEY=value', 'export KEY="value"' and '# comments'.
+// Values are taken literally, there is no expansion of variables.
+fn read_dotenv(path: &Path) -> Result<Vec<(String, String)>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut vars = Vec::new();
+    for (n, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = match line.strip_prefix("export ") {
+            Some(line) => line.trim_start(),
+            None => line,
+        };
+
+        let (key, value) = parse_pair(line).ok_or_else(|| {
+            Error::new(format!(
+                "Line {} of '{}' is not a KEY=VALUE pair",
+                n + 1,
+                path.to_string_lossy()
+            ))
+            .with_code(EXIT_BROKEN_DATA)
+        })?;
+        vars.push((key, unquote(value.trim())));
+    }
+    Ok(vars)
+}
+
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        return value[1..value.len() - 1].to_owned();
+    }
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        return value[1..value.len() - 1]
+            .replace("\\n", "\n")
+            .replace("\\\"", "\"")
+            .replace("\\\\", "\\");
+    }
+    value.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_pair, unquote, Environment, Origin};
+    use std::env;
+    use std::fs;
+
+    fn value(environment: &Environment, key: &str) -> Option<String> {
+        environment
+            .vars()
+            .iter()
+            .find(|v| v.0 == key)
+            .map(|v| v.1.clone())
+    }
+
+    #[test]
+    fn pairs_need_a_valid_name() {
+        assert_eq!(
+            parse_pair("RUST_LOG=debug=1"),
+            Some(("RUST_LOG".to_owned(), "debug=1".to_owned()))
+        );
+        assert_eq!(
+            parse_pair("EMPTY="),
+            Some(("EMPTY".to_owned(), "".to_owned()))
+        );
+        assert_eq!(parse_pair("1ST=a"), None);
+        assert_eq!(parse_pair("A-B=a"), None);
+        assert_eq!(parse_pair("NOVALUE"), None);
+    }
+
+    #[test]
+    fn quotes_are_removed() {
+        assert_eq!(unquote("'a $b'"), "a $b");
+        assert_eq!(unquote("\"a\\nb \\\"c\\\"\""), "a\nb \"c\"");
+        assert_eq!(unquote("plain"), "plain");
+    }
+
+    #[test]
+    fn nearest_dotenv_wins_and_overrides_win_over_it() {
+        let root = env::temp_dir().join(format!("chore-dotenv-{}", std::process::id()));
+        let sub = root.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(root.join(".env"), "# shared\nA=root\nB=root\n").unwrap();
+        fs::write(sub.join(".env"), "export B=\"sub\"\n").unwrap();
+
+        let mut environment = Environment { vars: Vec::new() };
+        environment.set("C", "task", Origin::Task);
+        environment.load_dotenv(&sub).unwrap();
+        environment.extend_overrides(&["C=cli".to_owned()]).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(value(&environment, "A"), Some("root".to_owned()));
+        assert_eq!(value(&environment, "B"), Some("sub".to_owned()));
+        assert_eq!(value(&environment, "C"), Some("cli".to_owned()));
+        assert!(environment.extend_overrides(&["C".to_owned()]).is_err());
+    }
+}
@@ -1,19 +1,30 @@
 use std::error;
-use std::error::Error as ErrorTrait;
 use std::fmt;
 use std::io;
 use std::result;
 
-use rocksdb;
-
+use crate::store::StoreError;
 use crate::task::TaskError;
 
 pub type Result<T> = result::Result<T, Error>;
 
+// Exit codes of chore itself, following sysexits.h.
+// When a task has been run, chore exits with the code of the task instead.
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_BROKEN_DATA: i32 = 65;
+pub const EXIT_NOT_FOUND: i32 = 66;
+pub const EXIT_DATABASE: i32 = 69;
+pub const EXIT_CANT_CREATE: i32 = 73;
+pub const EXIT_IO: i32 = 74;
+// A task which timed out, like timeout(1).
+pub const EXIT_TIMEOUT: i32 = 124;
+
 #[derive(Debug)]
 pub struct Error {
     message: String,
     suggest: String,
+    code: i32,
 }
 
 impl Error {
@@ -21,6 +32,7 @@ impl Error {
         Error {
             message: message.as_ref().to_owned(),
             suggest: String::new(),
+            code: EXIT_FAILURE,
         }
     }
 
@@ -28,8 +40,32 @@ impl Error {
         Error {
             message: message.as_ref().to_owned(),
             suggest: suggest.as_ref().to_owned(),
+            code: EXIT_FAILURE,
         }
     }
+
+    // exited is returned when a task has finished unsuccessfully.
+    // It has nothing to print, chore just exits with the same code.
+    pub fn exited(code: i32) -> Error {
+        Error {
+            message: String::new(),
+            suggest: String::new(),
+            code,
+        }
+    }
+
+    pub fn with_code(mut self, code: i32) -> Error {
+        self.code = code;
+        self
+    }
+
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.message.is_empty()
+    }
 }
 
 impl fmt::Display for Error {
@@ -43,33 +79,79 @@ impl error::Error for Error {
         self.message.as_str()
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn cause(&self) -> Option<&dyn error::Error> {
         None
     }
 }
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Error {
-        Error::new(err.description())
+        Error::new(err.to_string()).with_code(EXIT_IO)
     }
 }
 
 impl From<TaskError> for Error {
     fn from(err: TaskError) -> Error {
+        use colored::*;
         match err {
             TaskError::NotFound(s) => {
-                use colored::*;
                 let message = format!("Task '{}' doesn't exist", s.yellow());
-                Error::new(message)
+                Error::new(message).with_code(EXIT_NOT_FOUND)
+            }
+            TaskError::RevisionNotFound(n) => Error::with_suggest(
+                format!("Revision {} doesn't exist", n.to_string().yellow()),
+                "You can list the revisions of the task with 'chore log'".to_owned(),
+            )
+            .with_code(EXIT_NOT_FOUND),
+            TaskError::UnknownOption(ref key) if key.starts_with("param.") => Error::with_suggest(
+                format!("'{}' is not a parameter or its field", key.yellow()),
+                format!(
+                    "Fields of a parameter are {}",
+                    crate::params::FIELDS.join(", ")
+                ),
+            )
+            .with_code(EXIT_USAGE),
+            TaskError::UnknownOption(key) => Error::with_suggest(
+                format!("'{}' is not an option of a task", key.yellow()),
+                format!("Available options are {}", crate::task::OPTIONS.join(", ")),
+            )
+            .with_code(EXIT_USAGE),
+            TaskError::InvalidParam(message) => Error::with_suggest(
+                message,
+                "Parameters are declared like param.port.type=int".to_owned(),
+            )
+            .with_code(EXIT_USAGE),
+            TaskError::InvalidOption(key, value) => {
+                let suggest = match key.as_str() {
+                    "inherit" => "It should be true or false",
+                    "extension" => "It should be like 'sh' or 'py'",
+                    "cwd" => "It should be invoke, task or an absolute path",
+                    "timeout" => "It should be like 90, 30s, 10m or 2h",
+                    "schedule" => "It should be a cron expression like '0 3 * * *' or @daily",
+                    "watch" => {
+                        "It should be relative globs separated by commas, like src/**/*.rs,*.toml"
+                    }
+                    "depends" => "It should be names of tasks separated by commas",
+                    "env" => "Variables are set one by one, like env.RUST_LOG=debug",
+                    _ if key.starts_with("env.") => "The name should be like RUST_LOG",
+                    _ => "",
+                };
+                Error::with_suggest(
+                    format!("'{}' is invalid value for {}", value.yellow(), key),
+                    suggest.to_owned(),
+                )
+                .with_code(EXIT_USAGE)
             }
-            _ => Error::new(err.description()),
+            TaskError::InvalidPath => Error::new(err.to_string()).with_code(EXIT_USAGE),
+            TaskError::BrokenData => Error::new(err.to_string()).with_code(EXIT_BROKEN_DATA),
+            TaskError::DBOperationFailed(_) => Error::new(err.to_string()).with_code(EXIT_DATABASE),
         }
     }
 }
 
-impl From<rocksdb::Error> for Error {
-    fn from(err: rocksdb::Error) -> Error {
-        Error::new(err.description())
+impl From<StoreError> for Error {
+    fn from(err: StoreError) -> Error {
+        Error::new(err.to_string()).with_code(EXIT_DATABASE)
     }
 }
 
@@ -83,3 +165,31 @@ fn format_error(f: &mut fmt::Formatter, message: &str, suggest: &str) -> fmt::Re
     }
     write!(f, "{} {}", "error:".red().bold(), details,)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{Error, EXIT_BROKEN_DATA, EXIT_DATABASE, EXIT_IO, EXIT_NOT_FOUND};
+    use crate::store::StoreError;
+    use crate::task::TaskError;
+
+    #[test]
+    fn errors_map_to_their_exit_codes() {
+        let code = |err: Error| err.code();
+        assert_eq!(
+            code(TaskError::NotFound("a".to_owned()).into()),
+            EXIT_NOT_FOUND
+        );
+        assert_eq!(code(TaskError::BrokenData.into()), EXIT_BROKEN_DATA);
+        assert_eq!(code(StoreError::new("locked").into()), EXIT_DATABASE);
+        assert_eq!(code(io::Error::other("full").into()), EXIT_IO);
+    }
+
+    #[test]
+    fn exited_is_silent() {
+        let err = Error::exited(3);
+        assert!(err.is_silent());
+        assert_eq!(err.code(), 3);
+    }
+}
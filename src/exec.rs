@@ -0,0 +1,218 @@
+use std::env;
+use std::fs::{self, DirBuilder, File};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use libc;
+
+use crate::error::{Error, Result};
+use crate::task::Task;
+use crate::tempfile::{self, TempFile};
+
+// Script is the content of a task ready to be executed.
+// On Linux it lives in an anonymous memory file, so nothing is written to a file system
+// and noexec mounts don't matter. Otherwise it is written to a private runtime directory.
+pub enum Script {
+    Memory(File),
+    File(TempFile),
+}
+
+impl Script {
+    pub fn create(content: &[u8], ext: &str) -> Result<Script> {
+        if let Some(file) = memfd(content) {
+            return Ok(Script::Memory(file));
+        }
+
+        let mut file = TempFile::create_executable(runtime_dir()?, &tempfile::random_name(ext))?;
+        file.write_all(content)?;
+        file.close();
+        Ok(Script::File(file))
+    }
+
+    fn path(&self) -> PathBuf {
+        match *self {
+            Script::Memory(ref file) => {
+                PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()))
+            }
+            Script::File(ref file) => file.path().to_path_buf(),
+        }
+    }
+
+    // command builds a command which executes the script, or passes it to the interpreter.
+    // The arguments added to it come right after, so the first one is $1 of the script.
+    pub fn command(&self, interpreter: &[String]) -> Command {
+        let mut cmd = match interpreter.split_first() {
+            Some((program, args)) => {
+                let mut cmd = Command::new(program);
+                cmd.args(args).arg(self.path());
+                cmd
+            }
+            None => Command::new(self.path()),
+        };
+
+        // Executing /proc/self/fd/N is what fexecve does. The descriptor is
+        // close-on-exec, so it is inherited only by this child, which needs it
+        // to exec the script or for the interpreter to read it.
+        if let Script::Memory(ref file) = *self {
+            let fd = file.as_raw_fd();
+            unsafe {
+                cmd.pre_exec(move || {
+                    libc::fcntl(fd, libc::F_SETFD, 0);
+                    Ok(())
+                });
+            }
+        }
+        cmd
+    }
+}
+
+// Interpreters for the tasks without a shebang, by extension.
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("sh", "sh"),
+    ("bash", "bash"),
+    ("zsh", "zsh"),
+    ("py", "python3"),
+    ("rb", "ruby"),
+    ("js", "node"),
+    ("pl", "perl"),
+];
+
+// interpreter resolves the command line which runs the task.
+// The interpreter option of the task comes first, then the shebang, which is represented
+// by an empty command line, and then the extension. The last resort is sh, as shells do.
+pub fn interpreter(task: &Task) -> Vec<String> {
+    if !task.interpreter().is_empty() {
+        return task
+            .interpreter()
+            .split_whitespace()
+            .map(|s| s.to_owned())
+            .collect();
+    }
+
+    if task.content().starts_with(b"#!") {
+        return Vec::new();
+    }
+
+    let ext = task.extension().trim_start_matches('.');
+    let program = INTERPRETERS
+        .iter()
+        .find(|&&(e, _)| e == ext)
+        .map(|&(_, program)| program)
+        .unwrap_or("sh");
+    vec![program.to_owned()]
+}
+
+// shebang returns the interpreter line of the content without '#!'.
+pub fn shebang(content: &[u8]) -> Option<String> {
+    if !content.starts_with(b"#!") {
+        return None;
+    }
+    let line = content[2..].split(|&b| b == b'\n').next().unwrap_or(&[]);
+    Some(String::from_utf8_lossy(line).trim().to_owned())
+}
+
+// memfd writes the content into an anonymous memory file and returns a read-only handle to it.
+// A writable descriptor must not be left open, or exec fails with ETXTBSY.
+#[cfg(target_os = "linux")]
+fn memfd(content: &[u8]) -> Option<File> {
+    if !Path::new("/proc/self/fd").is_dir() {
+        return None;
+    }
+
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_memfd_create,
+            b"chore\0".as_ptr(),
+            libc::MFD_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return None;
+    }
+
+    let mut writer = unsafe { File::from_raw_fd(fd as i32) };
+    writer.write_all(content).ok()?;
+    File::open(format!("/proc/self/fd/{}", writer.as_raw_fd())).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn memfd(_: &[u8]) -> Option<File> {
+    None
+}
+
+// runtime_dir returns a directory only the current user can access.
+// It is $XDG_RUNTIME_DIR/chore, or chore-<uid> in the temp directory.
+fn runtime_dir() -> Result<PathBuf> {
+    let uid = unsafe { libc::getuid() };
+    let dir = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(ref dir) if !dir.is_empty() => Path::new(dir).join("chore"),
+        _ => env::temp_dir().join(format!("chore-{}", uid)),
+    };
+
+    match DirBuilder::new().recursive(true).mode(0o700).create(&dir) {
+        Ok(_) => (),
+        Err(ref e) if e.kind() == std::io::ErrorKind::AlreadyExists => (),
+        Err(e) => return Err(e.into()),
+    }
+
+    // Someone else may have created it first in a shared temp directory.
+    let meta = fs::symlink_metadata(&dir)?;
+    if !meta.is_dir() || meta.uid() != uid || meta.permissions().mode() & 0o077 != 0 {
+        return Err(Error::with_suggest(
+            format!("'{}' is not a private directory", dir.to_string_lossy()),
+            "Remove it or set XDG_RUNTIME_DIR to a directory only you can access".to_owned(),
+        ));
+    }
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{interpreter, shebang, Script};
+    use crate::task::Task;
+
+    fn task(extension: &str, content: &str) -> Task {
+        let mut task = Task::at("/a/task");
+        task.set_extension(extension);
+        task.set_content(content.as_bytes().to_vec());
+        task
+    }
+
+    #[test]
+    fn first_argument_is_dollar_one() {
+        let script = Script::create(b"#!/bin/sh\ntest \"$1\" = a && test $# = 2\n", "sh").unwrap();
+        let status = script.command(&[]).args(["a", "b"]).status().unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn interpreter_by_option_shebang_and_extension() {
+        let mut with_option = task("py", "#!/bin/sh");
+        with_option.set_interpreter("python3 -u");
+        assert_eq!(interpreter(&with_option), vec!["python3", "-u"]);
+        assert!(interpreter(&task("py", "#!/usr/bin/env python3\n")).is_empty());
+        assert_eq!(interpreter(&task(".py", "print(1)")), vec!["python3"]);
+        assert_eq!(interpreter(&task("txt", "echo")), vec!["sh"]);
+    }
+
+    #[test]
+    fn shebang_line() {
+        assert_eq!(
+            shebang(b"#! /bin/bash -e\necho"),
+            Some("/bin/bash -e".to_owned())
+        );
+        assert_eq!(shebang(b"echo"), None);
+    }
+
+    #[test]
+    fn script_is_given_to_the_interpreter() {
+        let script = Script::create(b"import sys; sys.exit(int(sys.argv[1]))", "py").unwrap();
+        let program = interpreter(&task("py", ""));
+        let status = script.command(&program).arg("3").status().unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+}
@@ -0,0 +1,138 @@
+use colored::*;
+
+use crate::error::{Error, Result, EXIT_USAGE};
+use crate::task::Task;
+
+// Node is a task to run and the indices of the nodes it depends on, which come before it.
+pub struct Node {
+    pub task: Task,
+    pub depends: Vec<usize>,
+}
+
+// order resolves the dependencies of roots into the order they should run.
+// Every task comes after the ones it depends on, and the roots come in the given order
+// unless one depends on another. A task which is depended on several times appears once.
+// Tasks are identified by their path.
+pub fn order<F>(roots: Vec<Task>, mut depends: F) -> Result<Vec<Node>>
+where
+    F: FnMut(&Task) -> Result<Vec<Task>>,
+{
+    let mut nodes = Vec::new();
+    let mut stack = Vec::new();
+    for root in roots {
+        visit(root, &mut depends, &mut nodes, &mut stack)?;
+    }
+    Ok(nodes)
+}
+
+fn visit<F>(
+    task: Task,
+    depends: &mut F,
+    nodes: &mut Vec<Node>,
+    stack: &mut Vec<Task>,
+) -> Result<usize>
+where
+    F: FnMut(&Task) -> Result<Vec<Task>>,
+{
+    if let Some(index) = nodes.iter().position(|n| n.task.path() == task.path()) {
+        return Ok(index);
+    }
+    if let Some(start) = stack.iter().position(|t| t.path() == task.path()) {
+        return Err(cycle(&stack[start..], &task));
+    }
+
+    let children = depends(&task)?;
+    stack.push(task);
+    let mut indices = Vec::new();
+    for child in children {
+        let index = visit(child, depends, nodes, stack)?;
+        if !indices.contains(&index) {
+            indices.push(index);
+        }
+    }
+    let task = stack.pop().unwrap();
+
+    nodes.push(Node {
+        task,
+        depends: indices,
+    });
+    Ok(nodes.len() - 1)
+}
+
+fn cycle(path: &[Task], task: &Task) -> Error {
+    let names: Vec<&str> = path.iter().chain(Some(task)).map(|t| t.name()).collect();
+    Error::with_suggest(
+        format!(
+            "Tasks depend on each other: {}",
+            names.join(" -> ").yellow()
+        ),
+        format!(
+            "Drop one of them from the dependencies, like 'chore set {} depends=...'",
+            path.last().map(|t| t.name()).unwrap_or_default()
+        ),
+    )
+    .with_code(EXIT_USAGE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{order, Node};
+    use crate::error::{Result, EXIT_USAGE};
+    use crate::task::Task;
+
+    // resolve orders roots in /w, where a task depends on the tasks listed for it.
+    fn resolve(roots: &[&str], graph: &[(&str, &[&str])]) -> Result<Vec<Node>> {
+        let graph: HashMap<&str, &[&str]> = graph.iter().cloned().collect();
+        let task = |name: &str| Task::at(format!("/w/{}", name));
+        order(roots.iter().map(|name| task(name)).collect(), |t| {
+            Ok(graph
+                .get(t.name())
+                .map(|names| names.iter().map(|name| task(name)).collect())
+                .unwrap_or_default())
+        })
+    }
+
+    fn names(nodes: &[Node]) -> Vec<&str> {
+        nodes.iter().map(|n| n.task.name()).collect()
+    }
+
+    #[test]
+    fn dependencies_come_first() {
+        let nodes = resolve(
+            &["deploy"],
+            &[("deploy", &["build", "test"]), ("test", &["build"])],
+        )
+        .unwrap();
+        assert_eq!(names(&nodes), vec!["build", "test", "deploy"]);
+        assert_eq!(nodes[1].depends, vec![0]);
+        assert_eq!(nodes[2].depends, vec![0, 1]);
+    }
+
+    #[test]
+    fn roots_keep_their_order() {
+        let nodes = resolve(&["lint", "test", "build"], &[]).unwrap();
+        assert_eq!(names(&nodes), vec!["lint", "test", "build"]);
+
+        let nodes = resolve(&["test", "build"], &[("test", &["build"])]).unwrap();
+        assert_eq!(names(&nodes), vec!["build", "test"]);
+    }
+
+    #[test]
+    fn shared_dependency_appears_once() {
+        let nodes = resolve(&["a", "b"], &[("a", &["c"]), ("b", &["c"]), ("c", &[])]).unwrap();
+        assert_eq!(names(&nodes), vec!["c", "a", "b"]);
+        assert_eq!(nodes[2].depends, vec![0]);
+    }
+
+    #[test]
+    fn cycle_is_an_error() {
+        let err = resolve(&["a"], &[("a", &["b"]), ("b", &["c"]), ("c", &["a"])])
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), EXIT_USAGE);
+
+        assert!(resolve(&["a"], &[("a", &["a"])]).is_err());
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use toml;
+
+use crate::error::{Error, Result, EXIT_BROKEN_DATA};
+use crate::params::Param;
+use crate::task::Task;
+
+// FILE_NAME is a task file which lives in a repository, next to the code it works on.
+pub const FILE_NAME: &str = ".chore.toml";
+
+#[derive(Deserialize)]
+struct LocalFile {
+    #[serde(default)]
+    tasks: BTreeMap<String, LocalTask>,
+}
+
+// A task is either a bare script or a table with options. The table is boxed, since it is
+// far larger than a script.
+//
+//   [tasks]
+//   test = "cargo test"
+//
+//   [tasks.build]
+//   run = "cargo build --release"
+//   inherit = true
+//   description = "Build the release binary"
+//   tags = ["ci"]
+//   env = { RUSTFLAGS = "-D warnings" }
+//   depends = ["test"]
+//   params = [{ name = "target", type = "string", default = "x86_64" }]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LocalTask {
+    Script(String),
+    Table(Box<Table>),
+}
+
+#[derive(Deserialize)]
+struct Table {
+    run: String,
+    #[serde(default)]
+    inherit: bool,
+    #[serde(default)]
+    extension: String,
+    #[serde(default)]
+    interpreter: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    cwd: String,
+    #[serde(default)]
+    timeout: String,
+    #[serde(default)]
+    watch: Vec<String>,
+    #[serde(default)]
+    schedule: String,
+    #[serde(default)]
+    depends: Vec<String>,
+    #[serde(default)]
+    params: Vec<Param>,
+}
+
+// load reads the tasks defined in the task file of dir.
+// A missing file has no task.
+pub fn load(dir: &Path) -> Result<Vec<Task>> {
+    let path = dir.join(FILE_NAME);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let file: LocalFile = toml::from_str(&content).map_err(|e| {
+        Error::with_suggest(
+            format!("Unable to read '{}'", path.to_string_lossy()),
+            e.to_string(),
+        )
+        .with_code(EXIT_BROKEN_DATA)
+    })?;
+
+    let mut tasks = Vec::new();
+    for (name, local) in file.tasks {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(Error::new(format!(
+                "'{}' in '{}' is invalid name for a task",
+                name,
+                path.to_string_lossy()
+            )));
+        }
+
+        let mut task = Task::at(dir.join(&name));
+        match local {
+            LocalTask::Script(run) => task.set_content(run.into_bytes()),
+            LocalTask::Table(table) => {
+                let Table {
+                    run,
+                    inherit,
+                    extension,
+                    interpreter,
+                    description,
+                    tags,
+                    env,
+                    cwd,
+                    timeout,
+                    watch,
+                    schedule,
+                    depends,
+                    params,
+                } = *table;
+                // The daemon only knows the tasks of the database, so a schedule here would never fire.
+                if !schedule.trim().is_empty() {
+                    return Err(Error::with_suggest(
+                        format!(
+                            "'{}' in '{}' can't have a schedule",
+                            name,
+                            path.to_string_lossy()
+                        ),
+                        "chore daemon runs only the tasks of the database. Schedule one there with 'chore set <task> schedule=...'".to_owned(),
+                    )
+                    .with_code(EXIT_BROKEN_DATA));
+                }
+                task.set_content(run.into_bytes());
+                task.set_inherit(inherit);
+                task.set_extension(&extension);
+                task.set_interpreter(&interpreter);
+                task.set_description(&description);
+                task.set_tags(&tags);
+                for (key, value) in env {
+                    task.set_env(&key, &value);
+                }
+                task.set_option("cwd", &cwd)
+                    .and_then(|_| task.set_option("timeout", &timeout))
+                    .and_then(|_| task.set_option("watch", &watch.join(",")))
+                    .and_then(|_| task.set_option("depends", &depends.join(",")))
+                    .and_then(|_| task.set_params(params))
+                    .map_err(|e| Error::from(e).with_code(EXIT_BROKEN_DATA))?;
+            }
+        }
+        task.set_source(&path);
+        tasks.push(task);
+    }
+    Ok(tasks)
+}
+
+// find returns the task of the name defined in the task file of dir.
+pub fn find(dir: &Path, name: &str) -> Result<Option<Task>> {
+    Ok(load(dir)?.into_iter().find(|t| t.name() == name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use super::{load, FILE_NAME};
+    use crate::exec::{interpreter, Script};
+
+    fn dir_with(name: &str, content: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("chore-local-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(FILE_NAME), content).unwrap();
+        dir
+    }
+
+    #[test]
+    fn loads_scripts_and_tables() {
+        let dir = dir_with(
+            "load",
+            "[tasks]\ntest = \"cargo test\"\n\n[tasks.build]\nrun = \"cargo build\"\ninherit = true\n",
+        );
+        let tasks = load(&dir).unwrap();
+        let names: Vec<&str> = tasks.iter().map(|t| t.name()).collect();
+        assert_eq!(names, vec!["build", "test"]);
+        assert!(tasks[0].inherit());
+        assert_eq!(tasks[1].content(), b"cargo test");
+        assert_eq!(tasks[1].source(), Some(dir.join(FILE_NAME).as_path()));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn run_is_given_to_the_interpreter_of_the_extension() {
+        let dir = dir_with(
+            "python",
+            "[tasks.check]\nrun = \"import sys; sys.exit(sys.version_info[0])\"\nextension = \"py\"\n",
+        );
+        let task = load(&dir).unwrap().remove(0);
+        let _ = fs::remove_dir_all(&dir);
+
+        let script = Script::create(task.content(), task.extension()).unwrap();
+        let status = script.command(&interpreter(&task)).status().unwrap();
+        assert_eq!(status.code(), Some(3));
+    }
+
+    #[test]
+    fn names_with_a_slash_are_rejected() {
+        let dir = dir_with("name", "[tasks]\n\"a/b\" = \"true\"\n");
+        assert!(load(&dir).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn schedules_are_rejected() {
+        let dir = dir_with(
+            "schedule",
+            "[tasks.backup]\nrun = \"true\"\nschedule = \"@daily\"\n",
+        );
+        assert!(load(&dir).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn missing_file_has_no_task() {
+        let dir = env::temp_dir().join(format!("chore-local-{}-missing", process::id()));
+        assert!(load(&dir).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::error::{Error, Result, EXIT_CANT_CREATE};
+use crate::time;
+
+// How many logs are kept for each task. The oldest ones are removed when a new one is created.
+const LOGS_PER_TASK: usize = 20;
+
+// END_MARK starts the last line of a finished log, so a follower knows when to stop.
+pub const END_MARK: &str = "end |";
+
+// LogFile is the output of a run, one line for each line of the output, like
+// '2018-05-01 12:00:00 out | Compiling chore'.
+pub struct LogFile {
+    number: u64,
+    file: Mutex<File>,
+}
+
+impl LogFile {
+    // create starts the next log of the task in dir.
+    pub fn create(dir: &Path, task_path: &str) -> Result<LogFile> {
+        let task_dir = task_dir(dir, task_path);
+        fs::create_dir_all(&task_dir).map_err(|e| unwritable(&task_dir, e))?;
+
+        let mut numbers = runs(dir, task_path)?;
+        let number = numbers.last().map(|n| n + 1).unwrap_or(1);
+        let path = task_dir.join(format!("{}.log", number));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .map_err(|e| unwritable(&path, e))?;
+
+        numbers.push(number);
+        if numbers.len() > LOGS_PER_TASK {
+            for old in &numbers[..numbers.len() - LOGS_PER_TASK] {
+                let _ = fs::remove_file(task_dir.join(format!("{}.log", old)));
+            }
+        }
+
+        Ok(LogFile {
+            number,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn number(&self) -> u64 {
+        self.number
+    }
+
+    // line appends a line of the stream, which is out or err.
+    // Writing is best effort, a full disk doesn't stop the task.
+    pub fn line(&self, stream: &str, text: &str) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{} {} | {}", time::format(time::now()), stream, text);
+        }
+    }
+
+    // finish writes the last line with the exit code.
+    pub fn finish(&self, exit_code: i32) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(
+                file,
+                "{} {} exit {}",
+                time::format(time::now()),
+                END_MARK,
+                exit_code
+            );
+        }
+    }
+}
+
+// is_end tells whether the line is the last one of a log.
+pub fn is_end(line: &str) -> bool {
+    let stamp = "YYYY-MM-DD hh:mm:ss ".len();
+    line.get(stamp..)
+        .map(|l| l.starts_with(END_MARK))
+        .unwrap_or(false)
+}
+
+// path is where the log of the run is.
+pub fn path(dir: &Path, task_path: &str, number: u64) -> PathBuf {
+    task_dir(dir, task_path).join(format!("{}.log", number))
+}
+
+// runs returns the numbers of the kept logs of the task, oldest first.
+pub fn runs(dir: &Path, task_path: &str) -> Result<Vec<u64>> {
+    let entries = match fs::read_dir(task_dir(dir, task_path)) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut numbers = Vec::new();
+    for entry in entries {
+        // The logs of the tasks under this one are in directories next to its logs.
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if let Some(Ok(n)) = name.strip_suffix(".log").map(|n| n.parse::<u64>()) {
+            numbers.push(n);
+        }
+    }
+    numbers.sort();
+    Ok(numbers)
+}
+
+// task_dir is where the logs of a task are, like 'home/me/build' in dir for '/home/me/build'.
+// Every directory is a part of the task path, so none is longer than a file name can be.
+fn task_dir(dir: &Path, task_path: &str) -> PathBuf {
+    let mut task_dir = dir.to_owned();
+    task_dir.extend(task_path.split('/').filter(|part| !part.is_empty()));
+    task_dir
+}
+
+fn unwritable(path: &Path, e: io::Error) -> Error {
+    Error::with_suggest(
+        format!(
+            "Unable to write a log to '{}': {}",
+            path.to_string_lossy(),
+            e
+        ),
+        "Set CHORE_LOG_DIR to a directory you can write to".to_owned(),
+    )
+    .with_code(EXIT_CANT_CREATE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_end, path, runs, LogFile, LOGS_PER_TASK};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn logs_are_numbered_and_the_oldest_removed() {
+        let dir = env::temp_dir().join(format!("chore-logs-{}", std::process::id()));
+        for _ in 0..LOGS_PER_TASK + 2 {
+            let log = LogFile::create(&dir, "/a/build").unwrap();
+            log.line("out", "hello");
+            log.finish(0);
+        }
+        let numbers = runs(&dir, "/a/build").unwrap();
+        let content = fs::read_to_string(path(&dir, "/a/build", 3)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(numbers.len(), LOGS_PER_TASK);
+        assert_eq!(numbers[0], 3);
+        let lines: Vec<&str> = content.lines().collect();
+        assert!(lines[0].ends_with(" out | hello"));
+        assert!(!is_end(lines[0]));
+        assert!(is_end(lines[1]));
+        assert!(runs(&dir, "/a/test").unwrap().is_empty());
+    }
+
+    #[test]
+    fn logs_of_long_paths_are_kept() {
+        let dir = env::temp_dir().join(format!("chore-logs-long-{}", std::process::id()));
+        let parent = format!("/{}/{}", "a".repeat(200), "b".repeat(200));
+        let child = format!("{}/test", parent);
+        LogFile::create(&dir, &parent).unwrap().finish(0);
+        LogFile::create(&dir, &child).unwrap().finish(0);
+        LogFile::create(&dir, &child).unwrap().finish(0);
+        let parent_runs = runs(&dir, &parent).unwrap();
+        let child_runs = runs(&dir, &child).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(parent_runs, vec![1]);
+        assert_eq!(child_runs, vec![1, 2]);
+    }
+}
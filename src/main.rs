@@ -1,39 +1,84 @@
 #[macro_use]
 extern crate clap;
 extern crate colored;
+extern crate libc;
 extern crate rand;
+#[cfg(feature = "rocksdb")]
 extern crate rocksdb;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate toml;
 
 use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use clap::ErrorKind;
 
 mod app;
+mod bundle;
 mod command;
+mod cron;
 mod db;
+mod diff;
+mod environment;
 mod error;
+mod exec;
+mod graph;
+mod local;
+mod logs;
+mod params;
 mod path;
+mod schema;
+mod search;
+mod signal;
+mod store;
 mod task;
 mod tempfile;
+mod time;
+mod watch;
 
 use crate::command::Cmd;
 
 fn main() {
-    let matches = app::Chore::initialize().get_matches();
+    let matches = match app::Chore::initialize().get_matches_safe() {
+        Ok(matches) => matches,
+        Err(e) => match e.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => {
+                println!("{}", e.message);
+                return;
+            }
+            _ => {
+                eprintln!("{}", e.message);
+                process::exit(error::EXIT_USAGE);
+            }
+        },
+    };
+    signal::install();
+
     let subcmd = matches.subcommand_name().unwrap();
     let submatches = matches.subcommand_matches(subcmd).unwrap();
+    let db = match db::Config::resolve(submatches.value_of("db"), submatches.value_of("backend")) {
+        Ok(db) => db,
+        Err(e) => exit(&e),
+    };
 
-    let cmd: Box<Cmd> = match subcmd {
+    let cmd: Box<dyn Cmd> = match subcmd {
         "new" => {
             let task = submatches.value_of("task").unwrap().to_owned();
             let ext = extract_extension(&task).unwrap_or_else(|| "sh".to_owned());
             Box::new(command::New {
+                db,
                 dir: env::current_dir().unwrap(),
                 task,
                 ext,
                 inherit: submatches.is_present("inherit"),
+                interpreter: submatches
+                    .value_of("interpreter")
+                    .unwrap_or_default()
+                    .to_owned(),
                 filename: submatches
                     .value_of("filename")
                     .map(|v| v.to_owned())
@@ -51,35 +96,209 @@ fn main() {
             })
         }
         "edit" => Box::new(command::Edit {
+            db,
+            dir: env::current_dir().unwrap(),
             task: submatches.value_of("task").unwrap().to_owned(),
         }),
-        "run" => Box::new(command::Run {
+        "run" => {
+            let mut values: Vec<String> = submatches
+                .values_of("task")
+                .unwrap()
+                .map(|s| s.to_owned())
+                .collect();
+            let parallel = submatches.is_present("parallel");
+            let jobs = match submatches.value_of("jobs") {
+                Some(jobs) => match app::parse_jobs(jobs) {
+                    Ok(jobs) => jobs,
+                    Err(message) => exit(&error::Error::new(message).with_code(error::EXIT_USAGE)),
+                },
+                None => 0,
+            };
+            let args = if parallel {
+                Vec::new()
+            } else {
+                values.split_off(1)
+            };
+            Box::new(command::Run {
+                db,
+                dir: env::current_dir().unwrap(),
+                tasks: values,
+                args,
+                env: submatches
+                    .values_of("env")
+                    .map(|values| values.map(|s| s.to_owned()).collect())
+                    .unwrap_or_default(),
+                dotenv: submatches.is_present("dotenv"),
+                print_env: submatches.is_present("print_env"),
+                keep_going: submatches.is_present("keep_going"),
+                parallel,
+                jobs,
+                log: submatches.is_present("log"),
+                timeout: submatches
+                    .value_of("timeout")
+                    .and_then(time::parse_duration),
+                watch: if submatches.is_present("watch") {
+                    Some(
+                        submatches
+                            .values_of("watch")
+                            .map(|values| values.map(|s| s.to_owned()).collect())
+                            .unwrap_or_default(),
+                    )
+                } else {
+                    None
+                },
+                on_change: command::OnChange::from_name(
+                    submatches.value_of("on_change").unwrap_or("restart"),
+                ),
+            })
+        }
+        "show" => Box::new(command::Show {
+            db,
             dir: env::current_dir().unwrap(),
             task: submatches.value_of("task").unwrap().to_owned(),
-            args: submatches
-                .values_of("args")
+        }),
+        "set" => Box::new(command::Set {
+            db,
+            task: submatches.value_of("task").unwrap().to_owned(),
+            options: submatches
+                .values_of("options")
                 .map(|values| values.map(|s| s.to_owned()).collect())
-                .unwrap_or_else(Vec::new),
+                .unwrap_or_default(),
         }),
-        "show" => Box::new(command::Show {
+        "unset" => Box::new(command::Unset {
+            db,
             task: submatches.value_of("task").unwrap().to_owned(),
+            keys: submatches
+                .values_of("keys")
+                .map(|values| values.map(|s| s.to_owned()).collect())
+                .unwrap_or_default(),
         }),
         "rename" => Box::new(command::Rename {
+            db,
             from: submatches.value_of("task").unwrap().to_owned(),
             to: submatches.value_of("new_name").unwrap().to_owned(),
         }),
         "rm" => Box::new(command::Remove {
+            db,
+            dir: env::current_dir().unwrap(),
             task: submatches.value_of("task").unwrap().to_owned(),
         }),
+        "alias" => Box::new(command::Alias {
+            db,
+            dir: env::current_dir().unwrap(),
+            name: submatches.value_of("name").map(|v| v.to_owned()),
+            task: submatches.value_of("task").map(|v| v.to_owned()),
+            inherit: submatches.is_present("inherit"),
+            delete: submatches.is_present("delete"),
+        }),
         "ls" => Box::new(command::List {
+            db,
             dir: env::current_dir().unwrap(),
+            long: submatches.is_present("long"),
+            tag: submatches.value_of("tag").map(|v| v.to_owned()),
+        }),
+        "mv-dir" => Box::new(command::MoveDir {
+            db,
+            from: PathBuf::from(submatches.value_of("from").unwrap()),
+            to: PathBuf::from(submatches.value_of("to").unwrap()),
+            recursive: submatches.is_present("recursive"),
+            conflict: command::Conflict::from_name(
+                submatches.value_of("conflict").unwrap_or("prompt"),
+            ),
+        }),
+        "cp-dir" => Box::new(command::CopyDir {
+            db,
+            from: PathBuf::from(submatches.value_of("from").unwrap()),
+            to: PathBuf::from(submatches.value_of("to").unwrap()),
+            recursive: submatches.is_present("recursive"),
+            conflict: command::Conflict::from_name(
+                submatches.value_of("conflict").unwrap_or("prompt"),
+            ),
         }),
+        "history" => Box::new(command::History {
+            db,
+            dir: env::current_dir().unwrap(),
+            task: submatches.value_of("task").map(|v| v.to_owned()),
+            failed: submatches.is_present("failed"),
+            since: submatches
+                .value_of("since")
+                .and_then(|v| time::parse_since(v, time::now())),
+            count: value_t!(submatches, "count", usize).unwrap_or(20),
+            output: submatches.is_present("output"),
+        }),
+        "logs" => Box::new(command::Logs {
+            db,
+            dir: env::current_dir().unwrap(),
+            task: submatches.value_of("task").unwrap().to_owned(),
+            run: value_t!(submatches, "run", u64).ok(),
+            follow: submatches.is_present("follow"),
+        }),
+        "doctor" => Box::new(command::Doctor {
+            db,
+            root: submatches
+                .value_of("root")
+                .map(PathBuf::from)
+                .or_else(|| env::var_os("HOME").map(PathBuf::from))
+                .unwrap_or_else(|| PathBuf::from("/")),
+            check: submatches.is_present("check"),
+        }),
+        "export" => Box::new(command::Export {
+            db,
+            dir: env::current_dir().unwrap(),
+            recursive: submatches.is_present("recursive"),
+            output: submatches.value_of("output").map(PathBuf::from),
+        }),
+        "import" => Box::new(command::Import {
+            db,
+            file: PathBuf::from(submatches.value_of("file").unwrap()),
+            into: submatches
+                .value_of("into")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| env::current_dir().unwrap()),
+            conflict: command::Conflict::from_name(
+                submatches.value_of("conflict").unwrap_or("prompt"),
+            ),
+        }),
+        "log" => Box::new(command::Log {
+            db,
+            task: submatches.value_of("task").unwrap().to_owned(),
+        }),
+        "diff" => Box::new(command::Diff {
+            db,
+            task: submatches.value_of("task").unwrap().to_owned(),
+            from: value_t!(submatches, "from", u64).ok(),
+            to: value_t!(submatches, "to", u64).ok(),
+        }),
+        "revert" => Box::new(command::Revert {
+            db,
+            task: submatches.value_of("task").unwrap().to_owned(),
+            revision: value_t_or_exit!(submatches, "revision", u64),
+        }),
+        "daemon" => Box::new(command::Daemon { db }),
+        "schedule" => match submatches.subcommand() {
+            ("ls", Some(_)) => Box::new(command::ScheduleList { db }),
+            _ => return,
+        },
+        "db" => match submatches.subcommand() {
+            ("migrate", Some(m)) => Box::new(command::Migrate {
+                db,
+                dry_run: m.is_present("dry_run"),
+            }),
+            _ => return,
+        },
         _ => return,
     };
 
     if let Err(e) = cmd.run() {
-        println!("{}", e);
+        exit(&e);
+    }
+}
+
+fn exit(e: &error::Error) -> ! {
+    if !e.is_silent() {
+        eprintln!("{}", e);
     }
+    process::exit(e.code())
 }
 
 fn extract_extension(task_name: &str) -> Option<String> {
@@ -0,0 +1,482 @@
+use std::path::Path;
+use std::result;
+
+use colored::*;
+
+use crate::error::{Error, Result, EXIT_USAGE};
+use crate::task::Task;
+
+// FIELDS are the fields of a parameter which can be set as param.NAME.FIELD.
+pub const FIELDS: &[&str] = &["type", "default", "required", "choices", "help"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    #[default]
+    String,
+    Int,
+    Float,
+    Bool,
+    Path,
+}
+
+impl Kind {
+    pub fn from_name(name: &str) -> Option<Kind> {
+        match name {
+            "string" | "" => Some(Kind::String),
+            "int" => Some(Kind::Int),
+            "float" => Some(Kind::Float),
+            "bool" => Some(Kind::Bool),
+            "path" => Some(Kind::Path),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::String => "string",
+            Kind::Int => "int",
+            Kind::Float => "float",
+            Kind::Bool => "bool",
+            Kind::Path => "path",
+        }
+    }
+}
+
+// Param is a named argument a task declares, given as --name value to 'chore run'.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Param {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub kind: Kind,
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub choices: Vec<String>,
+    #[serde(default)]
+    pub help: String,
+}
+
+impl Param {
+    pub fn new(name: &str) -> Param {
+        Param {
+            name: name.to_owned(),
+            kind: Kind::String,
+            default: None,
+            required: false,
+            choices: Vec::new(),
+            help: String::new(),
+        }
+    }
+
+    pub fn is_valid_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        match chars.next() {
+            Some(c) if c.is_ascii_lowercase() => (),
+            _ => return false,
+        }
+        name != "help"
+            && chars.all(|c| c == '-' || c == '_' || c.is_ascii_lowercase() || c.is_ascii_digit())
+    }
+
+    // check tells whether the parameter is consistent, like the default is one of the choices.
+    pub fn check(&self) -> result::Result<(), String> {
+        if !Param::is_valid_name(&self.name) {
+            return Err(format!("'{}' is invalid name for a parameter", self.name));
+        }
+        if self.kind == Kind::Bool && !self.choices.is_empty() {
+            return Err(format!(
+                "'{}' is a bool, which can't have choices",
+                self.name
+            ));
+        }
+        for choice in &self.choices {
+            self.validate(choice)?;
+        }
+        if let Some(ref default) = self.default {
+            self.validate(default)?;
+        }
+        Ok(())
+    }
+
+    // validate checks a value against the type and the choices.
+    pub fn validate(&self, value: &str) -> result::Result<(), String> {
+        let valid = match self.kind {
+            Kind::String | Kind::Path => true,
+            Kind::Int => value.parse::<i64>().is_ok(),
+            Kind::Float => value.parse::<f64>().is_ok(),
+            Kind::Bool => parse_bool(value).is_some(),
+        };
+        if !valid {
+            return Err(format!(
+                "'{}' is not {} {} for --{}",
+                value,
+                if self.kind == Kind::Int { "an" } else { "a" },
+                self.kind.name(),
+                self.name
+            ));
+        }
+        if !self.choices.is_empty() && !self.choices.iter().any(|c| c == value) {
+            return Err(format!(
+                "'{}' is not one of {} for --{}",
+                value,
+                self.choices.join(", "),
+                self.name
+            ));
+        }
+        Ok(())
+    }
+
+    // env_name is the variable which holds the value, like CHORE_ARG_DRY_RUN for dry-run.
+    pub fn env_name(&self) -> String {
+        format!("CHORE_ARG_{}", self.name.replace('-', "_").to_uppercase())
+    }
+
+    // summary prints the parameter in a line, like 'port:int=8080'.
+    pub fn summary(&self) -> String {
+        let mut summary = format!("{}:{}", self.name, self.kind.name());
+        if !self.choices.is_empty() {
+            summary.push_str(&format!("({})", self.choices.join("|")));
+        }
+        if let Some(ref default) = self.default {
+            summary.push_str(&format!("={}", default));
+        }
+        if self.required {
+            summary.push('!');
+        }
+        summary
+    }
+
+    fn value_name(&self) -> String {
+        if self.choices.is_empty() {
+            format!("<{}>", self.kind.name())
+        } else {
+            format!("<{}>", self.choices.join("|"))
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+// Arguments are the validated values of the parameters, in the order they are declared,
+// and the arguments which are passed through as they are.
+pub struct Arguments {
+    pub values: Vec<(Param, Option<String>)>,
+    pub rest: Vec<String>,
+}
+
+// Parsed is either the arguments for the task or a request for its usage.
+pub enum Parsed {
+    Arguments(Arguments),
+    Help,
+}
+
+// parse reads '--name value', '--name=value' and '--flag' for bool parameters.
+// Anything after '--' and the values which aren't options are passed through.
+pub fn parse(task: &Task, args: &[String], invoke_dir: &Path) -> Result<Parsed> {
+    let params = task.params();
+    let mut values: Vec<Option<String>> = vec![None; params.len()];
+    let mut rest = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            rest.extend(args.cloned());
+            break;
+        }
+        if arg == "--help" || arg == "-h" {
+            return Ok(Parsed::Help);
+        }
+        if !arg.starts_with("--") {
+            rest.push(arg.clone());
+            continue;
+        }
+
+        let mut kv = arg[2..].splitn(2, '=');
+        let name = kv.next().unwrap_or_default();
+        let inline = kv.next().map(|v| v.to_owned());
+        let (index, negated) = match params.iter().position(|p| p.name == name) {
+            Some(index) => (index, false),
+            None if name.starts_with("no-") => match params
+                .iter()
+                .position(|p| p.kind == Kind::Bool && p.name == name["no-".len()..])
+            {
+                Some(index) if inline.is_none() => (index, true),
+                _ => return Err(usage_error(task, format!("Unknown option '{}'", arg))),
+            },
+            None => return Err(usage_error(task, format!("Unknown option '{}'", arg))),
+        };
+
+        let param = &params[index];
+        let value = match inline {
+            Some(value) => value,
+            None if param.kind == Kind::Bool => (!negated).to_string(),
+            None => match args.next() {
+                Some(value) => value.clone(),
+                None => return Err(usage_error(task, format!("--{} needs a value", param.name))),
+            },
+        };
+        param.validate(&value).map_err(|e| usage_error(task, e))?;
+        values[index] = Some(value);
+    }
+
+    let mut validated = Vec::new();
+    for (param, value) in params.iter().zip(values) {
+        let value = value.or_else(|| param.default.clone());
+        let value = match value {
+            Some(value) => Some(normalize(param, &value, invoke_dir)),
+            None if param.kind == Kind::Bool => Some(false.to_string()),
+            None if param.required => {
+                return Err(usage_error(task, format!("--{} is required", param.name)))
+            }
+            None => None,
+        };
+        validated.push((param.clone(), value));
+    }
+
+    Ok(Parsed::Arguments(Arguments {
+        values: validated,
+        rest,
+    }))
+}
+
+// normalize puts a value in the form the script gets: true or false for a bool,
+// and an absolute path for a path relative to where chore was invoked.
+fn normalize(param: &Param, value: &str, invoke_dir: &Path) -> String {
+    match param.kind {
+        Kind::Bool => parse_bool(value).unwrap_or(false).to_string(),
+        Kind::Path => invoke_dir.join(value).to_string_lossy().into_owned(),
+        _ => value.to_owned(),
+    }
+}
+
+fn usage_error(task: &Task, message: String) -> Error {
+    Error::with_suggest(message, format!("See 'chore run {} --help'", task.name()))
+        .with_code(EXIT_USAGE)
+}
+
+// usage renders the help of a task from its description and parameters.
+pub fn usage(task: &Task) -> String {
+    let params = task.params();
+    let mut usage = String::new();
+    if !task.description().is_empty() {
+        usage.push_str(&format!("{}\n\n", task.description()));
+    }
+    usage.push_str(&format!(
+        "{}\n    chore run {} [OPTIONS] [--] [args]...\n",
+        "USAGE:".yellow(),
+        task.name()
+    ));
+
+    let lefts: Vec<String> = params
+        .iter()
+        .map(|p| match p.kind {
+            Kind::Bool => format!("--{}", p.name),
+            _ => format!("--{} {}", p.name, p.value_name()),
+        })
+        .collect();
+    let width = lefts.iter().map(|l| l.len()).max().unwrap_or(0);
+
+    usage.push_str(&format!("\n{}\n", "OPTIONS:".yellow()));
+    for (param, left) in params.iter().zip(&lefts) {
+        let mut notes = Vec::new();
+        if param.required {
+            notes.push("required".to_owned());
+        }
+        if let Some(ref default) = param.default {
+            notes.push(format!("default: {}", default));
+        }
+        let mut help = param.help.clone();
+        if !notes.is_empty() {
+            if !help.is_empty() {
+                help.push(' ');
+            }
+            help.push_str(&format!("[{}]", notes.join(", ")));
+        }
+        let line = format!("    {:2$}    {}", left, help, width);
+        usage.push_str(&format!("{}\n", line.trim_end()));
+    }
+    usage.push_str(&format!(
+        "    {:1$}    Prints this help\n",
+        "-h, --help", width
+    ));
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{parse, Arguments, Kind, Param, Parsed};
+    use crate::error::EXIT_USAGE;
+    use crate::task::Task;
+
+    fn param(name: &str, kind: Kind) -> Param {
+        let mut param = Param::new(name);
+        param.kind = kind;
+        param
+    }
+
+    // deploy declares --target, --port=8080 and --dry-run.
+    fn deploy() -> Task {
+        let mut target = param("target", Kind::String);
+        target.choices = vec!["staging".to_owned(), "production".to_owned()];
+        target.required = true;
+        let mut port = param("port", Kind::Int);
+        port.default = Some("8080".to_owned());
+
+        let mut task = Task::at("/w/deploy");
+        task.set_params(vec![target, port, param("dry-run", Kind::Bool)])
+            .unwrap();
+        task
+    }
+
+    fn arguments(args: &[&str]) -> Arguments {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        match parse(&deploy(), &args, Path::new("/w")) {
+            Ok(Parsed::Arguments(arguments)) => arguments,
+            Ok(Parsed::Help) => panic!("help for {:?}", args),
+            Err(e) => panic!("{} for {:?}", e, args),
+        }
+    }
+
+    fn values(arguments: &Arguments) -> Vec<Option<&str>> {
+        arguments
+            .values
+            .iter()
+            .map(|(_, value)| value.as_ref().map(|v| v.as_str()))
+            .collect()
+    }
+
+    fn error_code(args: &[&str]) -> i32 {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        match parse(&deploy(), &args, Path::new("/w")) {
+            Err(e) => e.code(),
+            Ok(_) => panic!("{:?} should fail", args),
+        }
+    }
+
+    #[test]
+    fn values_come_in_declared_order_with_defaults() {
+        let parsed = arguments(&["--dry-run", "--target", "staging"]);
+        assert_eq!(
+            values(&parsed),
+            vec![Some("staging"), Some("8080"), Some("true")]
+        );
+
+        let parsed = arguments(&["--target=production", "--port=9000"]);
+        assert_eq!(
+            values(&parsed),
+            vec![Some("production"), Some("9000"), Some("false")]
+        );
+    }
+
+    #[test]
+    fn bool_can_be_negated() {
+        let parsed = arguments(&["--target", "staging", "--dry-run", "--no-dry-run"]);
+        assert_eq!(values(&parsed)[2], Some("false"));
+        let parsed = arguments(&["--target", "staging", "--dry-run=off"]);
+        assert_eq!(values(&parsed)[2], Some("false"));
+    }
+
+    #[test]
+    fn other_arguments_pass_through() {
+        let parsed = arguments(&["a", "--target", "staging", "b", "--", "--port", "c"]);
+        assert_eq!(parsed.rest, vec!["a", "b", "--port", "c"]);
+        assert_eq!(values(&parsed)[1], Some("8080"));
+    }
+
+    #[test]
+    fn invalid_arguments_are_usage_errors() {
+        assert_eq!(error_code(&[]), EXIT_USAGE);
+        assert_eq!(error_code(&["--target", "dev"]), EXIT_USAGE);
+        assert_eq!(
+            error_code(&["--target", "staging", "--port", "x"]),
+            EXIT_USAGE
+        );
+        assert_eq!(error_code(&["--target", "staging", "--port"]), EXIT_USAGE);
+        assert_eq!(
+            error_code(&["--target", "staging", "--verbose"]),
+            EXIT_USAGE
+        );
+        assert_eq!(
+            error_code(&["--target", "staging", "--no-port"]),
+            EXIT_USAGE
+        );
+    }
+
+    #[test]
+    fn help_is_asked_for() {
+        for args in &[vec!["--help"], vec!["--dry-run", "-h"]] {
+            let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+            match parse(&deploy(), &args, Path::new("/w")) {
+                Ok(Parsed::Help) => (),
+                _ => panic!("{:?} should ask for the usage", args),
+            }
+        }
+    }
+
+    #[test]
+    fn path_is_made_absolute() {
+        let mut task = Task::at("/w/copy");
+        task.set_params(vec![param("to", Kind::Path)]).unwrap();
+        let args = vec!["--to".to_owned(), "out/a".to_owned()];
+        match parse(&task, &args, Path::new("/home/me")) {
+            Ok(Parsed::Arguments(parsed)) => {
+                assert_eq!(values(&parsed), vec![Some("/home/me/out/a")])
+            }
+            _ => panic!("--to should be parsed"),
+        }
+    }
+
+    #[test]
+    fn values_are_checked_against_type_and_choices() {
+        assert!(param("n", Kind::Int).validate("-3").is_ok());
+        assert!(param("n", Kind::Int).validate("1.5").is_err());
+        assert!(param("x", Kind::Float).validate("1.5").is_ok());
+        assert!(param("b", Kind::Bool).validate("yes").is_ok());
+        assert!(param("b", Kind::Bool).validate("maybe").is_err());
+
+        let mut level = param("level", Kind::Int);
+        level.choices = vec!["1".to_owned(), "2".to_owned()];
+        assert!(level.validate("2").is_ok());
+        assert!(level.validate("3").is_err());
+    }
+
+    #[test]
+    fn inconsistent_params_are_rejected() {
+        assert!(Param::new("dry-run").check().is_ok());
+        assert!(Param::new("Dry").check().is_err());
+        assert!(Param::new("help").check().is_err());
+
+        let mut flag = param("flag", Kind::Bool);
+        flag.choices = vec!["true".to_owned()];
+        assert!(flag.check().is_err());
+
+        let mut port = param("port", Kind::Int);
+        port.default = Some("http".to_owned());
+        assert!(port.check().is_err());
+    }
+
+    #[test]
+    fn summary_is_a_line() {
+        let task = deploy();
+        let summaries: Vec<String> = task.params().iter().map(|p| p.summary()).collect();
+        assert_eq!(
+            summaries,
+            vec![
+                "target:string(staging|production)!",
+                "port:int=8080",
+                "dry-run:bool"
+            ]
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use serde_json::{self, Value};
+
+use crate::error::{Error, Result, EXIT_DATABASE};
+use crate::store::{Batch, TaskStore};
+
+// VERSION is bumped with a migration whenever the stored records change, even when older
+// records can be read as they are, so that an older chore refuses a database it would damage.
+pub const VERSION: u32 = 10;
+
+const VERSION_KEY: &str = "meta.schema_version";
+const TASK_PREFIX: &str = "task.";
+
+// A migration upgrades a single task record from `version - 1` to `version`.
+// It returns whether the record has been changed.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    apply: fn(&mut Value) -> bool,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Fill in the extension of tasks created before it was stored",
+        apply: v1_extension,
+    },
+    Migration {
+        version: 2,
+        description: "Fill in the interpreter of tasks",
+        apply: v2_interpreter,
+    },
+    Migration {
+        version: 3,
+        description: "Fill in the description, tags and timestamps of tasks",
+        apply: v3_details,
+    },
+    Migration {
+        version: 4,
+        description: "Fill in the environment variables of tasks",
+        apply: v4_env,
+    },
+    Migration {
+        version: 5,
+        description: "Fill in the run directory of tasks",
+        apply: v5_cwd,
+    },
+    Migration {
+        version: 6,
+        description: "Fill in the parameters of tasks",
+        apply: v6_params,
+    },
+    Migration {
+        version: 7,
+        description: "Fill in the dependencies of tasks",
+        apply: v7_depends,
+    },
+    Migration {
+        version: 8,
+        description: "Fill in the timeout of tasks",
+        apply: v8_timeout,
+    },
+    Migration {
+        version: 9,
+        description: "Fill in the watched files of tasks",
+        apply: v9_watch,
+    },
+    Migration {
+        version: 10,
+        description: "Fill in the schedule of tasks",
+        apply: v10_schedule,
+    },
+];
+
+fn v1_extension(record: &mut Value) -> bool {
+    fill(record, vec![("extension", Value::from(""))])
+}
+
+fn v2_interpreter(record: &mut Value) -> bool {
+    fill(record, vec![("interpreter", Value::from(""))])
+}
+
+fn v3_details(record: &mut Value) -> bool {
+    fill(
+        record,
+        vec![
+            ("description", Value::from("")),
+            ("tags", Value::Array(Vec::new())),
+            ("created_at", Value::from(0)),
+            ("updated_at", Value::from(0)),
+        ],
+    )
+}
+
+fn v4_env(record: &mut Value) -> bool {
+    fill(record, vec![("env", Value::Array(Vec::new()))])
+}
+
+fn v5_cwd(record: &mut Value) -> bool {
+    fill(record, vec![("cwd", Value::from(""))])
+}
+
+fn v6_params(record: &mut Value) -> bool {
+    fill(record, vec![("params", Value::Array(Vec::new()))])
+}
+
+fn v7_depends(record: &mut Value) -> bool {
+    fill(record, vec![("depends", Value::Array(Vec::new()))])
+}
+
+fn v8_timeout(record: &mut Value) -> bool {
+    fill(record, vec![("timeout", Value::from(0))])
+}
+
+fn v9_watch(record: &mut Value) -> bool {
+    fill(record, vec![("watch", Value::Array(Vec::new()))])
+}
+
+fn v10_schedule(record: &mut Value) -> bool {
+    fill(record, vec![("schedule", Value::from(""))])
+}
+
+// fill adds the fields a record lacks with their defaults, and tells whether any was added.
+fn fill(record: &mut Value, fields: Vec<(&str, Value)>) -> bool {
+    let obj = match record.as_object_mut() {
+        Some(obj) => obj,
+        None => return false,
+    };
+    let mut changed = false;
+    for (key, value) in fields {
+        if !obj.contains_key(key) {
+            obj.insert(key.to_owned(), value);
+            changed = true;
+        }
+    }
+    changed
+}
+
+pub struct Step {
+    pub version: u32,
+    pub description: &'static str,
+    pub changed: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub from: u32,
+    pub to: u32,
+    pub steps: Vec<Step>,
+    pub broken: Vec<String>,
+}
+
+impl Report {
+    pub fn is_up_to_date(&self) -> bool {
+        self.from == self.to
+    }
+}
+
+// upgrade migrates the database only when it is behind the current version,
+// so that opening an up-to-date database doesn't scan every task.
+pub fn upgrade(store: &dyn TaskStore) -> Result<()> {
+    if stored_version(store)? != Some(VERSION) {
+        migrate(store, false)?;
+    }
+    Ok(())
+}
+
+// migrate brings every record up to the current schema version in a single batch.
+// Nothing is written when dry_run is set, but the report is the same.
+pub fn migrate(store: &dyn TaskStore, dry_run: bool) -> Result<Report> {
+    let stored = stored_version(store)?;
+    let from = match stored {
+        Some(version) => version,
+        None if is_empty(store)? => VERSION,
+        None => 0,
+    };
+
+    if from > VERSION {
+        return Err(Error::with_suggest(
+            format!(
+                "The database has schema version {} but this chore only knows up to {}",
+                from, VERSION
+            ),
+            "Upgrade chore to use this database".to_owned(),
+        )
+        .with_code(EXIT_DATABASE));
+    }
+
+    let mut report = Report {
+        from,
+        to: VERSION,
+        ..Report::default()
+    };
+
+    let mut records = Vec::new();
+    for (key, value) in store.scan(TASK_PREFIX.as_bytes())? {
+        match serde_json::from_slice::<Value>(&value) {
+            Ok(record) => records.push((key, record, false)),
+            Err(_) => report.broken.push(task_path(&key)),
+        }
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > from) {
+        let mut step = Step {
+            version: migration.version,
+            description: migration.description,
+            changed: Vec::new(),
+        };
+        for &mut (ref key, ref mut record, ref mut dirty) in records.iter_mut() {
+            if (migration.apply)(record) {
+                step.changed.push(task_path(key));
+                *dirty = true;
+            }
+        }
+        report.steps.push(step);
+    }
+
+    if dry_run || report.is_up_to_date() && stored.is_some() {
+        return Ok(report);
+    }
+
+    let mut batch = Batch::default();
+    for (key, record, dirty) in records {
+        if dirty {
+            batch.put(&key, &serde_json::to_vec(&record).unwrap());
+        }
+    }
+    batch.put(VERSION_KEY.as_bytes(), VERSION.to_string().as_bytes());
+    store.write(batch)?;
+    Ok(report)
+}
+
+// task_path is the path of the task of the key, for the report only. The key itself is
+// written back as it is, even when it isn't valid UTF-8.
+fn task_path(key: &[u8]) -> String {
+    String::from_utf8_lossy(&key[TASK_PREFIX.len()..]).into_owned()
+}
+
+fn stored_version(store: &dyn TaskStore) -> Result<Option<u32>> {
+    match store.get(VERSION_KEY.as_bytes())? {
+        Some(v) => {
+            match String::from_utf8_lossy(&v).trim().parse() {
+                Ok(version) => Ok(Some(version)),
+                Err(_) => Err(Error::new("The schema version of the database is broken")
+                    .with_code(EXIT_DATABASE)),
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+fn is_empty(store: &dyn TaskStore) -> Result<bool> {
+    Ok(store.scan(TASK_PREFIX.as_bytes())?.next().is_none())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{migrate, VERSION, VERSION_KEY};
+    use crate::store::{MemoryStore, TaskStore};
+
+    const OLD_TASK: &[u8] = br#"{"inherit":false,"content":[]}"#;
+
+    #[test]
+    fn fills_in_old_records_and_stores_the_version() {
+        let store = MemoryStore::new();
+        store.put(b"task./a", OLD_TASK).unwrap();
+
+        let report = migrate(&store, false).unwrap();
+        assert_eq!((report.from, report.to), (0, VERSION));
+        assert_eq!(report.steps[0].changed, vec!["/a".to_owned()]);
+
+        let record = String::from_utf8(store.get(b"task./a").unwrap().unwrap()).unwrap();
+        assert!(record.contains(r#""extension":"""#));
+        let version = store.get(VERSION_KEY.as_bytes()).unwrap();
+        assert_eq!(version, Some(VERSION.to_string().into_bytes()));
+    }
+
+    #[test]
+    fn keys_which_are_not_utf8_are_kept() {
+        let store = MemoryStore::new();
+        store.put(b"task./caf\xe9", OLD_TASK).unwrap();
+
+        migrate(&store, false).unwrap();
+        let keys: Vec<Vec<u8>> = store.scan(b"task.").unwrap().map(|e| e.0).collect();
+        assert_eq!(keys, vec![b"task./caf\xe9".to_vec()]);
+    }
+
+    #[test]
+    fn dry_run_leaves_the_store_as_it_is() {
+        let store = MemoryStore::new();
+        store.put(b"task./a", OLD_TASK).unwrap();
+
+        let report = migrate(&store, true).unwrap();
+        assert_eq!(report.steps[0].changed, vec!["/a".to_owned()]);
+        assert_eq!(store.get(b"task./a").unwrap(), Some(OLD_TASK.to_vec()));
+        assert_eq!(store.get(VERSION_KEY.as_bytes()).unwrap(), None);
+    }
+
+    #[test]
+    fn refuses_a_newer_database() {
+        let store = MemoryStore::new();
+        let newer = (VERSION + 1).to_string();
+        store.put(VERSION_KEY.as_bytes(), newer.as_bytes()).unwrap();
+        assert!(migrate(&store, false).is_err());
+    }
+}
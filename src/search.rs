@@ -0,0 +1,173 @@
+use std::cmp;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// candidates looks for directories under root which may be the new location of a missing one.
+// A directory matches when it has the same name, or when its git remote points to
+// a repository of that name. Hidden directories are not visited.
+pub fn candidates<P: AsRef<Path>>(root: P, missing: &Path, depth: usize) -> Vec<PathBuf> {
+    let name = match missing.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => return Vec::new(),
+    };
+
+    let mut found = Vec::new();
+    walk(root.as_ref(), &name, depth, &mut found);
+    found
+}
+
+fn walk(dir: &Path, name: &str, depth: usize, found: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+        .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+        .map(|e| e.path())
+        .collect();
+    dirs.sort();
+
+    for path in dirs {
+        let same_name = path.file_name().map(|n| n == name).unwrap_or(false);
+        let same_remote = git_remote(&path)
+            .map(|url| repository_name(&url) == name)
+            .unwrap_or(false);
+        if same_name || same_remote {
+            found.push(path.clone());
+        }
+
+        if depth > 1 {
+            walk(&path, name, depth - 1, found);
+        }
+    }
+}
+
+// git_remote reads the url of the origin remote from .git/config without running git.
+pub fn git_remote(dir: &Path) -> Option<String> {
+    let config = fs::read_to_string(dir.join(".git").join("config")).ok()?;
+
+    let mut in_origin = false;
+    for line in config.lines().map(|l| l.trim()) {
+        if line.starts_with('[') {
+            in_origin = line == "[remote \"origin\"]";
+        } else if in_origin && line.starts_with("url") {
+            let url = line.split_once('=')?.1.trim();
+            return Some(url.to_owned());
+        }
+    }
+    None
+}
+
+// repository_name extracts 'chore' from urls like 'git@github.com:phynalle/chore.git'.
+fn repository_name(url: &str) -> &str {
+    let url = url.trim_end_matches('/');
+    let name = url.rsplit(['/', ':']).next().unwrap_or(url);
+    name.trim_end_matches(".git")
+}
+
+// similar returns the names which look like a mistyped name, closest first.
+// A name matches when it starts with the typed one, or when it is a few edits away,
+// which is a third of the length at most.
+pub fn similar<'a>(name: &str, names: &[&'a str], limit: usize) -> Vec<&'a str> {
+    let name = name.to_lowercase();
+    let mut ranked: Vec<(bool, usize, &str)> = names
+        .iter()
+        .filter_map(|&candidate| {
+            let lower = candidate.to_lowercase();
+            if lower == name {
+                return None;
+            }
+            let prefix = lower.starts_with(&name);
+            let distance = distance(&name, &lower);
+            let max_distance = cmp::max(1, cmp::max(name.len(), lower.len()) / 3);
+            if prefix || distance <= max_distance {
+                Some((!prefix, distance, candidate))
+            } else {
+                None
+            }
+        })
+        .collect();
+    ranked.sort();
+    ranked.dedup_by(|a, b| a.2 == b.2);
+    ranked.into_iter().take(limit).map(|r| r.2).collect()
+}
+
+// distance counts the edits between two strings in characters. Swapping two adjacent
+// characters is a single edit, like 'buidl' for 'build'.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows[i][j] is the distance between the first i characters of a and the first j of b.
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, d) in rows[0].iter_mut().enumerate() {
+        *d = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut d = cmp::min(rows[i - 1][j - 1] + cost, rows[i - 1][j] + 1);
+            d = cmp::min(d, rows[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = cmp::min(d, rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = d;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::process;
+
+    use super::{candidates, repository_name, similar};
+
+    #[test]
+    fn repository_name_of_remote_urls() {
+        assert_eq!(
+            repository_name("git@github.com:phynalle/chore.git"),
+            "chore"
+        );
+        assert_eq!(
+            repository_name("https://github.com/phynalle/chore/"),
+            "chore"
+        );
+        assert_eq!(repository_name("git@host:chore"), "chore");
+    }
+
+    #[test]
+    fn similar_names_by_prefix_or_edits() {
+        let names = ["build", "bench", "test", "deploy", "Build-docs"];
+        assert_eq!(similar("buidl", &names, 3), vec!["build"]);
+        assert_eq!(similar("bu", &names, 3), vec!["build", "Build-docs"]);
+        assert_eq!(similar("tset", &names, 3), vec!["test"]);
+        assert!(similar("xyz", &names, 3).is_empty());
+    }
+
+    #[test]
+    fn finds_directories_by_name_or_remote() {
+        let root = env::temp_dir().join(format!("chore-search-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("work/chore")).unwrap();
+        fs::create_dir_all(root.join("renamed/.git")).unwrap();
+        fs::create_dir_all(root.join(".hidden/chore")).unwrap();
+        fs::write(
+            root.join("renamed/.git/config"),
+            "[remote \"origin\"]\n\turl = git@github.com:phynalle/chore.git\n",
+        )
+        .unwrap();
+
+        let found = candidates(&root, Path::new("/old/chore"), 3);
+        assert_eq!(found, vec![root.join("renamed"), root.join("work/chore")]);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
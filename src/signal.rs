@@ -0,0 +1,210 @@
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use libc;
+
+use crate::tempfile;
+
+// SIGNALS are what chore passes on to the tasks it runs.
+const SIGNALS: &[libc::c_int] = &[libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+// How long a task has to exit after SIGTERM before it is killed.
+pub const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+// Process groups of the running tasks. A slot is zero when it is free.
+// They are atomics because the signal handler reads them.
+static GROUPS: [AtomicIsize; 64] = [const { AtomicIsize::new(0) }; 64];
+
+// The last signal chore received, zero for none.
+static RECEIVED: AtomicIsize = AtomicIsize::new(0);
+
+// Whether the running tasks are being stopped by stop_all.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+// install sets up the handler. While tasks are running, a signal is forwarded to them and chore
+// waits for them to exit, so it can clean up. Otherwise chore removes its temporary files and
+// dies of the signal as it would without the handler.
+pub fn install() {
+    for &signal in SIGNALS {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as usize;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+extern "C" fn handle(signal: libc::c_int) {
+    RECEIVED.store(signal as isize, Ordering::SeqCst);
+
+    let mut forwarded = false;
+    for group in GROUPS.iter() {
+        let pgid = group.load(Ordering::SeqCst);
+        if pgid > 0 {
+            unsafe {
+                libc::kill(-(pgid as libc::pid_t), signal);
+            }
+            forwarded = true;
+        }
+    }
+    if !forwarded {
+        // Only async-signal-safe calls from here.
+        tempfile::remove_all();
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
+
+// received returns the signal chore received, if any. Nothing more should be started then.
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(signal as i32),
+    }
+}
+
+// is_interrupted tells whether the user wants chore to stop, because chore received a signal
+// or the task was interrupted from the terminal.
+pub fn is_interrupted(exit_code: i32) -> bool {
+    received().is_some() || exit_code == 128 + libc::SIGINT
+}
+
+// is_stopping tells whether a run should start no more tasks, because chore received a signal
+// or the tasks are being stopped by stop_all.
+pub fn is_stopping() -> bool {
+    received().is_some() || STOPPING.load(Ordering::SeqCst)
+}
+
+// stop_all stops the running tasks like a timeout does, and keeps the runs from starting
+// other tasks until resume.
+pub fn stop_all() {
+    STOPPING.store(true, Ordering::SeqCst);
+    let groups: Vec<isize> = GROUPS
+        .iter()
+        .map(|g| g.load(Ordering::SeqCst))
+        .filter(|&pgid| pgid > 0)
+        .collect();
+    for &pgid in &groups {
+        kill(pgid, libc::SIGTERM);
+    }
+
+    thread::spawn(move || {
+        thread::sleep(GRACE_PERIOD);
+        for pgid in groups {
+            if GROUPS.iter().any(|g| g.load(Ordering::SeqCst) == pgid) {
+                kill(pgid, libc::SIGKILL);
+            }
+        }
+    });
+}
+
+pub fn resume() {
+    STOPPING.store(false, Ordering::SeqCst);
+}
+
+// is_foreground tells whether chore owns the terminal, so a task can take it over.
+pub fn is_foreground() -> bool {
+    unsafe { libc::isatty(0) == 1 && libc::tcgetpgrp(0) == libc::getpgrp() }
+}
+
+// isolate makes the task the leader of a new process group, so the task and whatever it starts
+// can be signaled together. In the foreground, the group takes over the terminal like a shell
+// does, which sends Ctrl-C to the task rather than to chore.
+pub fn isolate(cmd: &mut Command, foreground: bool) {
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setpgid(0, 0) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if foreground {
+                // A background group has to ignore SIGTTOU to take the terminal.
+                libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+                libc::tcsetpgrp(0, libc::getpid());
+                libc::signal(libc::SIGTTOU, libc::SIG_DFL);
+            }
+            Ok(())
+        });
+    }
+}
+
+// Exit is how a task ended.
+pub struct Exit {
+    pub status: ExitStatus,
+    pub timed_out: bool,
+}
+
+// wait waits for the task started with isolate. Signals chore receives meanwhile are forwarded
+// to its group. After timeout, the group gets SIGTERM, then SIGKILL after GRACE_PERIOD.
+pub fn wait(child: &mut Child, timeout: Option<Duration>, foreground: bool) -> io::Result<Exit> {
+    let pgid = child.id() as isize;
+    let slot = register(pgid);
+
+    let (done, finished) = mpsc::channel::<()>();
+    let (timed_out, watchdog) = mpsc::channel();
+    if let Some(timeout) = timeout {
+        thread::spawn(move || {
+            // The sender is dropped when the task exits, which ends the wait early.
+            if finished.recv_timeout(timeout) != Err(mpsc::RecvTimeoutError::Timeout) {
+                return;
+            }
+            let _ = timed_out.send(());
+            kill(pgid, libc::SIGTERM);
+            if finished.recv_timeout(GRACE_PERIOD) == Err(mpsc::RecvTimeoutError::Timeout) {
+                kill(pgid, libc::SIGKILL);
+            }
+        });
+    }
+
+    let status = child.wait();
+    drop(done);
+    unregister(slot);
+    if foreground {
+        take_terminal();
+    }
+    Ok(Exit {
+        status: status?,
+        timed_out: watchdog.try_recv().is_ok(),
+    })
+}
+
+fn register(pgid: isize) -> Option<usize> {
+    let slot = GROUPS.iter().position(|g| {
+        g.compare_exchange(0, pgid, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    });
+    // The signal may have come before the group was registered.
+    if let Some(signal) = received() {
+        kill(pgid, signal);
+    }
+    slot
+}
+
+fn unregister(slot: Option<usize>) {
+    if let Some(slot) = slot {
+        GROUPS[slot].store(0, Ordering::SeqCst);
+    }
+}
+
+fn kill(pgid: isize, signal: libc::c_int) {
+    unsafe {
+        libc::kill(-(pgid as libc::pid_t), signal);
+    }
+}
+
+// take_terminal brings chore back to the foreground after a task which took over the terminal.
+fn take_terminal() {
+    unsafe {
+        let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+        libc::tcsetpgrp(0, libc::getpgrp());
+        libc::signal(libc::SIGTTOU, previous);
+    }
+}
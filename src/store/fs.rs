@@ -0,0 +1,417 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use libc;
+use serde_json;
+
+use super::{Batch, Entries, Op, Result, StoreError, TaskStore};
+
+const SUFFIX: &str = ".json";
+
+// A batch is recorded in a journal in the root before it is applied. The journal is written
+// as '.batch.<pid>.<n>.tmp' and renamed to '.batch.<pid>.<n>' once complete, which commits the batch.
+const JOURNAL: &str = ".batch.";
+const UNCOMMITTED: &str = ".tmp";
+
+// The number of batches written by this process, which keeps the names of journals apart.
+static BATCHES: AtomicUsize = AtomicUsize::new(0);
+
+// Journal lists the changes of a batch by paths relative to the root: the staged files
+// with where they go, and the files to remove.
+#[derive(Serialize, Deserialize, Default)]
+struct Journal {
+    puts: Vec<(Vec<u8>, Vec<u8>)>,
+    deletes: Vec<Vec<u8>>,
+}
+
+// FsStore keeps each entry as a JSON file under a directory tree.
+// A key is split by '/' into directories, so 'task./home/me/build' is
+// stored in '<root>/task./home/me/build.json'.
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<FsStore> {
+        fs::create_dir_all(&root)?;
+        let store = FsStore {
+            root: root.as_ref().to_path_buf(),
+        };
+        store.recover()?;
+        Ok(store)
+    }
+
+    // recover finishes the batches which were committed by a writer that is gone,
+    // and throws away the ones it didn't get to commit.
+    fn recover(&self) -> Result<()> {
+        for entry in fs::read_dir(&self.root)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) if name.starts_with(JOURNAL) => name.to_owned(),
+                _ => continue,
+            };
+            let pid = name[JOURNAL.len()..].split('.').next().unwrap_or("");
+            if pid.parse().map(is_running).unwrap_or(false) {
+                continue;
+            }
+
+            if name.ends_with(UNCOMMITTED) {
+                if let Ok(journal) = self.read_journal(&path) {
+                    for (tmp, _) in &journal.puts {
+                        let _ = fs::remove_file(self.root.join(OsStr::from_bytes(tmp)));
+                    }
+                }
+            } else {
+                let journal = self.read_journal(&path)?;
+                self.apply(&journal)?;
+            }
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    fn read_journal(&self, path: &Path) -> Result<Journal> {
+        serde_json::from_slice(&fs::read(path)?).map_err(|e| {
+            StoreError::new(format!(
+                "Broken journal '{}': {}",
+                path.to_string_lossy(),
+                e
+            ))
+        })
+    }
+
+    // commit writes the journal and makes it take effect with a single rename.
+    fn commit(&self, journal: &Journal) -> Result<PathBuf> {
+        let n = BATCHES.fetch_add(1, Ordering::SeqCst);
+        let path = self
+            .root
+            .join(format!("{}{}.{}", JOURNAL, process::id(), n));
+        let mut tmp = path.as_os_str().to_os_string();
+        tmp.push(UNCOMMITTED);
+        let tmp = PathBuf::from(tmp);
+
+        let value = serde_json::to_vec(journal).map_err(|e| StoreError::new(e.to_string()))?;
+        let result = fs::File::create(&tmp)
+            .and_then(|mut file| file.write_all(&value).and_then(|_| file.sync_all()))
+            .and_then(|_| fs::rename(&tmp, &path))
+            .and_then(|_| fs::File::open(&self.root).and_then(|dir| dir.sync_all()));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&tmp);
+            return Err(e.into());
+        }
+        Ok(path)
+    }
+
+    // apply moves the staged files into place and removes the deleted ones. It can be
+    // repeated after a crash, since what was already done is skipped.
+    fn apply(&self, journal: &Journal) -> Result<()> {
+        for (tmp, path) in &journal.puts {
+            let tmp = self.root.join(OsStr::from_bytes(tmp));
+            match fs::rename(&tmp, self.root.join(OsStr::from_bytes(path))) {
+                Ok(_) => (),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        for path in &journal.deletes {
+            let path = self.root.join(OsStr::from_bytes(path));
+            match fs::remove_file(&path) {
+                Ok(_) => self.prune(&path),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn relative(&self, path: &Path) -> Vec<u8> {
+        path.strip_prefix(&self.root)
+            .unwrap_or(path)
+            .as_os_str()
+            .as_bytes()
+            .to_vec()
+    }
+
+    fn file_path(&self, key: &[u8]) -> Result<PathBuf> {
+        let mut path = self.root.clone();
+        let mut segments = key.split(|&b| b == b'/').peekable();
+        while let Some(segment) = segments.next() {
+            if segment.is_empty() || segment == b"." || segment == b".." {
+                return Err(StoreError::new(format!(
+                    "Invalid key: {}",
+                    String::from_utf8_lossy(key)
+                )));
+            }
+
+            if segments.peek().is_some() {
+                path.push(OsStr::from_bytes(segment));
+            } else {
+                let mut leaf = segment.to_vec();
+                leaf.extend_from_slice(SUFFIX.as_bytes());
+                path.push(OsStr::from_bytes(&leaf));
+            }
+        }
+        Ok(path)
+    }
+
+    fn collect(
+        &self,
+        dir: &Path,
+        key: &mut Vec<u8>,
+        entries: &mut Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<()> {
+        let read_dir = match fs::read_dir(dir) {
+            Ok(read_dir) => read_dir,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        for entry in read_dir {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.as_bytes();
+            let len = key.len();
+
+            if entry.file_type()?.is_dir() {
+                key.extend_from_slice(name);
+                key.push(b'/');
+                self.collect(&entry.path(), key, entries)?;
+            } else if name.ends_with(SUFFIX.as_bytes()) {
+                key.extend_from_slice(&name[..name.len() - SUFFIX.len()]);
+                entries.push((key.clone(), fs::read(entry.path())?));
+            }
+            key.truncate(len);
+        }
+        Ok(())
+    }
+
+    // stage writes the value next to path under a name which no other writer uses.
+    fn stage(&self, path: &Path, value: &[u8], n: usize) -> Result<PathBuf> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut tmp = path.as_os_str().to_os_string();
+        tmp.push(format!(".{}.{}.tmp", process::id(), n));
+        let tmp = PathBuf::from(tmp);
+
+        let mut file = fs::File::create(&tmp)?;
+        if let Err(e) = file.write_all(value).and_then(|_| file.sync_all()) {
+            let _ = fs::remove_file(&tmp);
+            return Err(e.into());
+        }
+        Ok(tmp)
+    }
+
+    fn prune(&self, path: &Path) {
+        let mut dir = path.parent();
+        while let Some(d) = dir {
+            if d == self.root || fs::remove_dir(d).is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+    }
+}
+
+impl TaskStore for FsStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.file_path(key)?) {
+            Ok(v) => Ok(Some(v)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn scan<'a>(&'a self, prefix: &[u8]) -> Result<Entries<'a>> {
+        // Walk the deepest directory which is fully covered by prefix.
+        let mut dir = self.root.clone();
+        let mut key = Vec::new();
+        if let Some(pos) = prefix.iter().rposition(|&b| b == b'/') {
+            for segment in prefix[..pos].split(|&b| b == b'/') {
+                dir.push(OsStr::from_bytes(segment));
+            }
+            key.extend_from_slice(&prefix[..=pos]);
+        }
+
+        let mut entries = Vec::new();
+        self.collect(&dir, &mut key, &mut entries)?;
+        entries.retain(|(k, _)| k.starts_with(prefix));
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    // Every put is staged in a temporary file of its own before anything is changed,
+    // so a failure while staging leaves the store untouched. The batch is then committed
+    // by its journal, and applied. A crash after the commit leaves the journal behind,
+    // and the next open finishes the batch, so either all of it or nothing is applied.
+    fn write(&self, batch: Batch) -> Result<()> {
+        // Only the last operation on a key counts.
+        let mut ops: Vec<(PathBuf, Option<Vec<u8>>)> = Vec::new();
+        for op in batch.into_ops() {
+            let (path, value) = match op {
+                Op::Put(key, value) => (self.file_path(&key)?, Some(value)),
+                Op::Delete(key) => (self.file_path(&key)?, None),
+            };
+            ops.retain(|(p, _)| *p != path);
+            ops.push((path, value));
+        }
+
+        let mut staged = Vec::new();
+        let mut journal = Journal::default();
+        for (path, value) in ops {
+            match value {
+                Some(value) => match self.stage(&path, &value, staged.len()) {
+                    Ok(tmp) => {
+                        journal
+                            .puts
+                            .push((self.relative(&tmp), self.relative(&path)));
+                        staged.push(tmp);
+                    }
+                    Err(e) => {
+                        for tmp in &staged {
+                            let _ = fs::remove_file(tmp);
+                        }
+                        return Err(e);
+                    }
+                },
+                None => journal.deletes.push(self.relative(&path)),
+            }
+        }
+
+        let committed = match self.commit(&journal) {
+            Ok(committed) => committed,
+            Err(e) => {
+                for tmp in &staged {
+                    let _ = fs::remove_file(tmp);
+                }
+                return Err(e);
+            }
+        };
+        self.apply(&journal)?;
+        fs::remove_file(committed)?;
+        Ok(())
+    }
+}
+
+// is_running tells whether the process of the pid still exists.
+fn is_running(pid: i32) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use std::process::Command;
+
+    use serde_json;
+
+    use super::{FsStore, Journal};
+    use crate::store::{Batch, TaskStore};
+
+    // left_by_a_crash writes a journal for a = 2 and b removed, as a writer which is gone left it.
+    fn left_by_a_crash(store: &FsStore, suffix: &str) {
+        let mut batch = Batch::default();
+        batch.put(b"task./a", b"1");
+        batch.put(b"task./b", b"1");
+        store.write(batch).unwrap();
+
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        fs::write(store.root.join("task./a.json.staged"), "2").unwrap();
+        let journal = Journal {
+            puts: vec![(b"task./a.json.staged".to_vec(), b"task./a.json".to_vec())],
+            deletes: vec![b"task./b.json".to_vec()],
+        };
+        fs::write(
+            store
+                .root
+                .join(format!(".batch.{}.0{}", child.id(), suffix)),
+            serde_json::to_vec(&journal).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn entries(store: &FsStore) -> Vec<(Vec<u8>, Vec<u8>)> {
+        store.scan(b"task./").unwrap().collect()
+    }
+
+    fn open(name: &str) -> FsStore {
+        let root = env::temp_dir().join(format!("chore-fs-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&root);
+        FsStore::open(root).unwrap()
+    }
+
+    #[test]
+    fn last_put_of_a_key_wins() {
+        let store = open("put");
+        let mut batch = Batch::default();
+        batch.put(b"task./a/b", b"1");
+        batch.put(b"task./a/b", b"2");
+        store.write(batch).unwrap();
+        assert_eq!(store.get(b"task./a/b").unwrap(), Some(b"2".to_vec()));
+        let _ = fs::remove_dir_all(&store.root);
+    }
+
+    #[test]
+    fn delete_after_put_removes_the_key() {
+        let store = open("delete");
+        let mut batch = Batch::default();
+        batch.put(b"task./a/b", b"1");
+        batch.put(b"task./a/c", b"2");
+        batch.delete(b"task./a/b");
+        store.write(batch).unwrap();
+
+        let keys: Vec<Vec<u8>> = store.scan(b"task./a/").unwrap().map(|e| e.0).collect();
+        assert_eq!(keys, vec![b"task./a/c".to_vec()]);
+        let _ = fs::remove_dir_all(&store.root);
+    }
+
+    #[test]
+    fn invalid_key_leaves_the_store_untouched() {
+        let store = open("invalid");
+        let mut batch = Batch::default();
+        batch.put(b"task./a/b", b"1");
+        batch.put(b"task./a/../c", b"2");
+        assert!(store.write(batch).is_err());
+        assert_eq!(store.get(b"task./a/b").unwrap(), None);
+        let _ = fs::remove_dir_all(&store.root);
+    }
+
+    #[test]
+    fn committed_batch_is_finished_on_open() {
+        let store = open("recover");
+        left_by_a_crash(&store, "");
+        let store = FsStore::open(&store.root).unwrap();
+        assert_eq!(entries(&store), vec![(b"task./a".to_vec(), b"2".to_vec())]);
+        assert_eq!(fs::read_dir(&store.root).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&store.root);
+    }
+
+    #[test]
+    fn uncommitted_batch_is_dropped_on_open() {
+        let store = open("drop");
+        left_by_a_crash(&store, ".tmp");
+        let store = FsStore::open(&store.root).unwrap();
+        assert_eq!(
+            entries(&store),
+            vec![
+                (b"task./a".to_vec(), b"1".to_vec()),
+                (b"task./b".to_vec(), b"1".to_vec()),
+            ]
+        );
+        assert_eq!(fs::read_dir(&store.root).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&store.root);
+    }
+}
@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use super::{Batch, Entries, Op, Result, TaskStore};
+
+// MemoryStore keeps everything in memory and loses it on exit.
+// It is mostly useful for tests and dry runs.
+#[derive(Default)]
+pub struct MemoryStore {
+    map: RefCell<BTreeMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl TaskStore for MemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.map.borrow().get(key).cloned())
+    }
+
+    fn scan<'a>(&'a self, prefix: &[u8]) -> Result<Entries<'a>> {
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = self
+            .map
+            .borrow()
+            .range(prefix.to_vec()..)
+            .take_while(|&(k, _)| k.starts_with(prefix))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn write(&self, batch: Batch) -> Result<()> {
+        let mut map = self.map.borrow_mut();
+        for op in batch.into_ops() {
+            match op {
+                Op::Put(key, value) => {
+                    map.insert(key, value);
+                }
+                Op::Delete(key) => {
+                    map.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryStore;
+    use crate::store::{Batch, TaskStore};
+
+    #[test]
+    fn applies_operations_in_order() {
+        let store = MemoryStore::new();
+        let mut batch = Batch::default();
+        batch.put(b"task./a/b", b"1");
+        batch.put(b"task./a/c", b"2");
+        batch.put(b"task./b", b"3");
+        batch.delete(b"task./a/b");
+        store.write(batch).unwrap();
+
+        let keys: Vec<Vec<u8>> = store.scan(b"task./a/").unwrap().map(|e| e.0).collect();
+        assert_eq!(keys, vec![b"task./a/c".to_vec()]);
+    }
+
+    #[test]
+    fn put_and_delete_a_single_entry() {
+        let store = MemoryStore::new();
+        store.put(b"task./a", b"1").unwrap();
+        assert_eq!(store.get(b"task./a").unwrap(), Some(b"1".to_vec()));
+        store.delete(b"task./a").unwrap();
+        assert_eq!(store.get(b"task./a").unwrap(), None);
+    }
+}
@@ -0,0 +1,101 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+mod fs;
+mod memory;
+#[cfg(feature = "rocksdb")]
+mod rocks;
+
+pub use self::fs::FsStore;
+pub use self::memory::MemoryStore;
+#[cfg(feature = "rocksdb")]
+pub use self::rocks::RocksStore;
+
+pub type Result<T> = result::Result<T, StoreError>;
+
+pub type Entries<'a> = Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a>;
+
+// TaskStore is an ordered key-value store which keeps every task.
+// Keys are compared bytewise, so scan must yield entries in ascending key order.
+pub trait TaskStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    // put and delete are shorthands for a batch of a single operation.
+    // The commands change several entries at once, so only the tests use them for now.
+    #[allow(dead_code)]
+    fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        let mut batch = Batch::default();
+        batch.put(key, value);
+        self.write(batch)
+    }
+
+    #[allow(dead_code)]
+    fn delete(&self, key: &[u8]) -> Result<()> {
+        let mut batch = Batch::default();
+        batch.delete(key);
+        self.write(batch)
+    }
+
+    // scan yields only the entries whose key starts with prefix.
+    fn scan<'a>(&'a self, prefix: &[u8]) -> Result<Entries<'a>>;
+
+    // write applies all operations of the batch at once.
+    fn write(&self, batch: Batch) -> Result<()>;
+}
+
+pub enum Op {
+    Put(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+}
+
+#[derive(Default)]
+pub struct Batch {
+    ops: Vec<Op>,
+}
+
+impl Batch {
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.ops.push(Op::Put(key.to_vec(), value.to_vec()));
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        self.ops.push(Op::Delete(key.to_vec()));
+    }
+
+    pub fn into_ops(self) -> Vec<Op> {
+        self.ops
+    }
+}
+
+#[derive(Debug)]
+pub struct StoreError {
+    message: String,
+}
+
+impl StoreError {
+    pub fn new<S: AsRef<str>>(message: S) -> StoreError {
+        StoreError {
+            message: message.as_ref().to_owned(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl error::Error for StoreError {}
+
+impl From<io::Error> for StoreError {
+    fn from(err: io::Error) -> StoreError {
+        StoreError::new(err.to_string())
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use rocksdb::{self, Options, DB};
+
+use super::{Batch, Entries, Op, Result, StoreError, TaskStore};
+
+pub struct RocksStore {
+    db: DB,
+}
+
+impl RocksStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<RocksStore> {
+        let mut db_opts = Options::default();
+        db_opts.create_if_missing(true);
+
+        let db = DB::open(&db_opts, path)?;
+        Ok(RocksStore { db })
+    }
+}
+
+impl TaskStore for RocksStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.db.get(key)?.map(|v| v.to_vec()))
+    }
+
+    fn scan<'a>(&'a self, prefix: &[u8]) -> Result<Entries<'a>> {
+        let prefix = prefix.to_vec();
+        let iter = self
+            .db
+            .prefix_iterator(&prefix)
+            .take_while(move |&(ref k, _)| k.starts_with(&prefix))
+            .map(|(k, v)| (k.into_vec(), v.into_vec()));
+        Ok(Box::new(iter))
+    }
+
+    fn write(&self, batch: Batch) -> Result<()> {
+        let mut wb = rocksdb::WriteBatch::default();
+        for op in batch.into_ops() {
+            match op {
+                Op::Put(key, value) => wb.put(&key, &value)?,
+                Op::Delete(key) => wb.delete(&key)?,
+            }
+        }
+        self.db.write(wb).map_err(|e| e.into())
+    }
+}
+
+impl From<rocksdb::Error> for StoreError {
+    fn from(err: rocksdb::Error) -> StoreError {
+        StoreError::new(err.to_string())
+    }
+}
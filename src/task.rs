@@ -1,21 +1,47 @@
+use std::collections::HashMap;
 use std::env;
 use std::error;
+use std::ffi::OsStr;
 use std::fmt;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
+use std::process;
 use std::result;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use rocksdb::{self, DB};
 use serde_json;
 
+use crate::cron::Schedule;
+use crate::params::{Kind, Param};
+use crate::store::{self, Entries, StoreError, TaskStore};
+
 type Result<T> = result::Result<T, TaskError>;
 
 pub struct TaskSystem {
-    db: DB,
+    db: Box<dyn TaskStore>,
+    revision_limit: usize,
+    history_limit: usize,
 }
 
 impl TaskSystem {
-    pub fn new(db: DB) -> TaskSystem {
-        TaskSystem { db }
+    pub fn new(db: Box<dyn TaskStore>) -> TaskSystem {
+        TaskSystem {
+            db,
+            revision_limit: 0,
+            history_limit: 0,
+        }
+    }
+
+    // keep_revisions limits how many revisions are kept for each task.
+    // The oldest ones are dropped on save. Zero means no limit.
+    pub fn keep_revisions(&mut self, limit: usize) {
+        self.revision_limit = limit;
+    }
+
+    // keep_history limits how many runs are kept in the history.
+    // The oldest ones are dropped when a run is recorded. Zero means no limit.
+    pub fn keep_history(&mut self, limit: usize) {
+        self.history_limit = limit;
     }
 
     fn normalize<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
@@ -59,51 +85,284 @@ impl TaskSystem {
         batch.commit()
     }
 
-    pub fn remove_task(&self, task: Task) -> Result<()> {
-        self.remove(task.path)
+    // set_last_run keeps when the task was last run apart from the task,
+    // so that running it doesn't rewrite the task.
+    pub fn set_last_run(&self, task: &Task, timestamp: u64) -> Result<()> {
+        let mut batch = store::Batch::default();
+        batch.put(
+            last_run_key(&task.path).as_bytes(),
+            &serde_json::to_vec(&timestamp)?,
+        );
+        self.db.write(batch).map_err(|e| e.into())
+    }
+
+    // last_run_at tells when the task was last run, zero for never.
+    pub fn last_run_at(&self, task: &Task) -> Result<u64> {
+        match self.db.get(last_run_key(&task.path).as_bytes())? {
+            Some(v) => serde_json::from_slice(&v).map_err(|e| e.into()),
+            None => Ok(0),
+        }
     }
 
     // scan is a expensive method so it should be used carefully.
-    pub fn scan<P: AsRef<Path>>(&self, path: P, current_only: bool) -> Result<ScanIterator> {
+    pub fn scan<P: AsRef<Path>>(&self, path: P, current_only: bool) -> Result<ScanIterator<'_>> {
         let mut prefix = TaskSystem::key(path)?;
         if !prefix.ends_with('/') {
             prefix.push('/');
         }
 
         Ok(ScanIterator {
-            inner: self.db.prefix_iterator(prefix.as_bytes()),
+            inner: self.db.scan(prefix.as_bytes())?,
             prefix,
             current_only,
         })
     }
 
-    pub fn batch(&self) -> WriteBatch {
+    // records returns every task in the database along with the broken ones, which scan skips.
+    // It walks the whole database so it is even more expensive than scan.
+    pub fn records(&self) -> Result<Vec<(PathBuf, Result<Task>)>> {
+        let mut records = Vec::new();
+        for (key, val) in self.db.scan(b"task.")? {
+            let path = PathBuf::from(OsStr::from_bytes(&key["task.".len()..]));
+            let task = if path.is_absolute() {
+                Task::from_slice(&path, &val)
+            } else {
+                Err(TaskError::InvalidPath)
+            };
+            records.push((path, task));
+        }
+        Ok(records)
+    }
+
+    // remove_record deletes a record as records returned it. The path isn't normalized,
+    // so a broken record with a relative path doesn't hit a task in the current directory.
+    pub fn remove_record(&self, path: &Path) -> Result<()> {
+        let mut key = b"task.".to_vec();
+        key.extend_from_slice(path.as_os_str().as_bytes());
+        let mut batch = store::Batch::default();
+        batch.delete(&key);
+        self.db.write(batch).map_err(|e| e.into())
+    }
+
+    // revisions returns every kept revision of the task, oldest first.
+    // They outlive the task itself, so a removed task can be reverted.
+    pub fn revisions<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Revision>> {
+        let abs_path = TaskSystem::normalize(path)?;
+        let prefix = format!("rev.{}/", abs_path.to_string_lossy());
+
+        let mut revisions = Vec::new();
+        for (key, val) in self.db.scan(prefix.as_bytes())? {
+            let number = &key[prefix.len()..];
+            if number.iter().all(|b| b.is_ascii_digit()) {
+                revisions.push(serde_json::from_slice(&val)?);
+            }
+        }
+        Ok(revisions)
+    }
+
+    pub fn revision<P: AsRef<Path>>(&self, path: P, number: u64) -> Result<Revision> {
+        let abs_path = TaskSystem::normalize(path)?;
+        match self
+            .db
+            .get(Revision::key(&abs_path.to_string_lossy(), number).as_bytes())?
+        {
+            Some(v) => serde_json::from_slice(&v).map_err(|e| e.into()),
+            None => Err(TaskError::RevisionNotFound(number)),
+        }
+    }
+
+    // record_run appends the run to the history.
+    pub fn record_run(&self, record: &RunRecord) -> Result<()> {
+        let mut batch = store::Batch::default();
+        batch.put(record.key().as_bytes(), &serde_json::to_vec(record)?);
+
+        let limit = self.history_limit;
+        if limit > 0 {
+            let keys: Vec<Vec<u8>> = self
+                .db
+                .scan(HISTORY_PREFIX.as_bytes())?
+                .map(|(k, _)| k)
+                .collect();
+            if keys.len() >= limit {
+                for key in &keys[..keys.len() + 1 - limit] {
+                    batch.delete(key);
+                }
+            }
+        }
+        self.db.write(batch).map_err(|e| e.into())
+    }
+
+    // history returns the recorded runs, oldest first. Broken records are skipped.
+    pub fn history(&self) -> Result<Vec<RunRecord>> {
+        let mut records: Vec<RunRecord> = self
+            .db
+            .scan(HISTORY_PREFIX.as_bytes())?
+            .filter_map(|(_, v)| serde_json::from_slice(&v).ok())
+            .collect();
+        records.sort_by_key(|r| r.started_at);
+        Ok(records)
+    }
+
+    pub fn alias<P: AsRef<Path>>(&self, path: P) -> Result<Option<Alias>> {
+        let key = Alias::key(&TaskSystem::normalize(path)?.to_string_lossy());
+        match self.db.get(key.as_bytes())? {
+            Some(v) => serde_json::from_slice(&v).map(Some).map_err(|e| e.into()),
+            None => Ok(None),
+        }
+    }
+
+    // aliases returns the aliases of dir, not of its subdirectories. Broken ones are skipped.
+    pub fn aliases<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<Alias>> {
+        let mut prefix = Alias::key(&TaskSystem::normalize(dir)?.to_string_lossy());
+        if !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        Ok(self
+            .db
+            .scan(prefix.as_bytes())?
+            .filter(|(k, _)| !k[prefix.len()..].contains(&b'/'))
+            .filter_map(|(_, v)| serde_json::from_slice(&v).ok())
+            .collect())
+    }
+
+    pub fn save_alias(&self, alias: &Alias) -> Result<()> {
+        let mut batch = store::Batch::default();
+        batch.put(
+            Alias::key(&alias.path).as_bytes(),
+            &serde_json::to_vec(alias)?,
+        );
+        self.db.write(batch).map_err(|e| e.into())
+    }
+
+    pub fn remove_alias<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let key = Alias::key(&TaskSystem::normalize(path)?.to_string_lossy());
+        let mut batch = store::Batch::default();
+        batch.delete(key.as_bytes());
+        self.db.write(batch).map_err(|e| e.into())
+    }
+
+    pub fn batch(&self) -> WriteBatch<'_> {
         WriteBatch {
             ts: self,
-            batch: rocksdb::WriteBatch::default(),
+            batch: store::Batch::default(),
+            revisions: HashMap::new(),
         }
     }
 }
 
 pub struct WriteBatch<'a> {
     ts: &'a TaskSystem,
-    batch: rocksdb::WriteBatch,
+    batch: store::Batch,
+    // The revisions of the tasks changed in this batch, including the staged ones.
+    revisions: HashMap<String, Vec<Revision>>,
 }
 
 impl<'a> WriteBatch<'a> {
+    // save stores the task and records a revision when its content or options changed.
+    // Only then updated_at is bumped, so recording a run doesn't count as a change.
     pub fn save(&mut self, task: &Task) -> Result<()> {
+        let mut revisions = self.revisions(&task.path)?;
+        let number = match revisions.last() {
+            Some(last) if last.same_as(&task.inner) => None,
+            Some(last) => Some(last.number + 1),
+            None => Some(1),
+        };
+
+        let mut inner = task.inner.clone();
+        let now = crate::time::now();
+        if inner.created_at == 0 {
+            inner.created_at = now;
+        }
+        if number.is_some() {
+            inner.updated_at = now;
+        }
+
         let key = format!("task.{}", task.path).into_bytes();
-        let value = serde_json::to_vec(&task.inner)?;
-        self.batch.put(&key, &value).map_err(|e| e.into())
+        let value = serde_json::to_vec(&inner)?;
+        self.batch.put(&key, &value);
+
+        if let Some(number) = number {
+            let revision = Revision::new(number, &inner);
+            self.batch.put(
+                Revision::key(&task.path, number).as_bytes(),
+                &serde_json::to_vec(&revision)?,
+            );
+            revisions.push(revision);
+        }
+        self.keep_revisions(&task.path, revisions);
+        Ok(())
     }
 
-    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let key = TaskSystem::key(path)?;
-        self.batch.delete(key.as_bytes()).map_err(|e| e.into())
+    // carry_revisions appends the revisions of the task at from to the ones at to, numbered
+
+    // carry_revisions appends the revisions of the task at from to the ones at to, numbered
+    // after them, so a moved or copied task keeps its history. Moving takes them from from.
+    pub fn carry_revisions(&mut self, from: &str, to: &str, remove_source: bool) -> Result<()> {
+        let source = self.revisions(from)?;
+        let mut revisions = self.revisions(to)?;
+        let last = revisions.last().map(|r| r.number).unwrap_or(0);
+        for (i, revision) in source.iter().enumerate() {
+            let mut revision = revision.clone();
+            revision.number = last + i as u64 + 1;
+            self.batch.put(
+                Revision::key(to, revision.number).as_bytes(),
+                &serde_json::to_vec(&revision)?,
+            );
+            revisions.push(revision);
+        }
+        self.keep_revisions(to, revisions);
+
+        if remove_source {
+            for revision in &source {
+                self.batch
+                    .delete(Revision::key(from, revision.number).as_bytes());
+            }
+            self.revisions.insert(from.to_owned(), Vec::new());
+        }
+        Ok(())
+    }
+
+    fn revisions(&self, path: &str) -> Result<Vec<Revision>> {
+        match self.revisions.get(path) {
+            Some(revisions) => Ok(revisions.clone()),
+            None => self.ts.revisions(path),
+        }
+    }
+
+    // keep_revisions drops the oldest revisions over the limit, and remembers the rest
+    // for the next change of the task in this batch.
+    fn keep_revisions(&mut self, path: &str, mut revisions: Vec<Revision>) {
+        let limit = self.ts.revision_limit;
+        if limit > 0 && revisions.len() > limit {
+            let excess = revisions.len() - limit;
+            for old in revisions.drain(..excess) {
+                self.batch
+                    .delete(Revision::key(path, old.number).as_bytes());
+            }
+        }
+        self.revisions.insert(path.to_owned(), revisions);
     }
 
-    pub fn remove_task(&mut self, task: Task) -> Result<()> {
-        self.remove(task.path)
+    // carry_last_run keeps when the task at from was last run for the task at to,
+    // unless that one was run later.
+    pub fn carry_last_run(&mut self, from: &Task, to: &Task) -> Result<()> {
+        let last_run_at = self.ts.last_run_at(from)?;
+        if last_run_at > self.ts.last_run_at(to)? {
+            self.batch.put(
+                last_run_key(&to.path).as_bytes(),
+                &serde_json::to_vec(&last_run_at)?,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let abs_path = TaskSystem::normalize(path)?;
+        let key = TaskSystem::key(&abs_path)?;
+        self.batch.delete(key.as_bytes());
+        self.batch
+            .delete(last_run_key(&abs_path.to_string_lossy()).as_bytes());
+        Ok(())
     }
 
     pub fn commit(self) -> Result<()> {
@@ -111,22 +370,19 @@ impl<'a> WriteBatch<'a> {
     }
 }
 
-pub struct ScanIterator {
-    inner: rocksdb::DBIterator,
+pub struct ScanIterator<'a> {
+    inner: Entries<'a>,
     prefix: String,
     current_only: bool,
 }
 
-impl Iterator for ScanIterator {
+impl<'a> Iterator for ScanIterator<'a> {
     type Item = Task;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             let (key, val) = self.inner.next()?;
-            let path = String::from_utf8(key.into_vec()).unwrap();
-            if !path.starts_with(&self.prefix) {
-                break None;
-            }
+            let path = String::from_utf8(key).unwrap();
 
             let relative_path = &path[self.prefix.len()..];
             if self.current_only && relative_path.contains('/') {
@@ -144,6 +400,8 @@ impl Iterator for ScanIterator {
 pub struct Task {
     name: String,
     path: String,
+    // The file which defines the task. None for a task in the database.
+    source: Option<PathBuf>,
     inner: Inner,
 }
 
@@ -155,6 +413,21 @@ impl Task {
         Task {
             name: name.to_owned(),
             path: path.into_os_string().into_string().unwrap(),
+            source: None,
+            inner: Inner::default(),
+        }
+    }
+
+    // at creates an empty task at the absolute path.
+    pub fn at<P: AsRef<Path>>(abs_path: P) -> Task {
+        let path = abs_path.as_ref();
+        Task {
+            name: path
+                .file_name()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            path: path.to_string_lossy().into_owned(),
+            source: None,
             inner: Inner::default(),
         }
     }
@@ -162,7 +435,7 @@ impl Task {
     fn from_slice<P: AsRef<Path>>(abs_path: P, v: &[u8]) -> Result<Task> {
         assert!(abs_path.as_ref().is_absolute());
 
-        let inner: Inner = serde_json::from_slice(&v)?;
+        let inner: Inner = serde_json::from_slice(v)?;
         let name = format!(
             "{}",
             abs_path
@@ -172,7 +445,12 @@ impl Task {
                 .to_string_lossy()
         );
         let path = format!("{}", abs_path.as_ref().to_string_lossy());
-        Ok(Task { inner, name, path })
+        Ok(Task {
+            inner,
+            name,
+            path,
+            source: None,
+        })
     }
 
     pub fn name(&self) -> &str {
@@ -183,6 +461,14 @@ impl Task {
         &self.path
     }
 
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    pub fn set_source<P: AsRef<Path>>(&mut self, source: P) {
+        self.source = Some(source.as_ref().to_path_buf());
+    }
+
     pub fn inherit(&self) -> bool {
         self.inner.inherit
     }
@@ -211,25 +497,567 @@ impl Task {
         &self.inner.extension
     }
 
+    // interpreter is the command line which runs the task, like 'bash -eu'.
+    // An empty one means it is decided by the shebang or the extension.
+    pub fn interpreter(&self) -> &str {
+        &self.inner.interpreter
+    }
+
+    pub fn set_interpreter(&mut self, interpreter: &str) {
+        self.inner.interpreter = interpreter.trim().to_owned();
+    }
+
+    pub fn description(&self) -> &str {
+        &self.inner.description
+    }
+
+    pub fn set_description(&mut self, description: &str) {
+        self.inner.description = description.trim().to_owned();
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.inner.tags
+    }
+
+    // set_tags keeps the order of the tags but drops the empty and duplicated ones.
+    pub fn set_tags<I, S>(&mut self, tags: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.inner.tags.clear();
+        for tag in tags {
+            let tag = tag.as_ref().trim();
+            if !tag.is_empty() && !self.has_tag(tag) {
+                self.inner.tags.push(tag.to_owned());
+            }
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.inner.tags.iter().any(|t| t == tag)
+    }
+
+    pub fn created_at(&self) -> u64 {
+        self.inner.created_at
+    }
+
+    pub fn updated_at(&self) -> u64 {
+        self.inner.updated_at
+    }
+
+    // env is the variables set for the task, in the order they were added.
+    pub fn env(&self) -> &[(String, String)] {
+        &self.inner.env
+    }
+
+    pub fn set_env(&mut self, key: &str, value: &str) {
+        match self.inner.env.iter_mut().find(|&&mut (ref k, _)| k == key) {
+            Some(var) => var.1 = value.to_owned(),
+            None => self.inner.env.push((key.to_owned(), value.to_owned())),
+        }
+    }
+
+    // depends are the names of the tasks which run before this one.
+    // They are looked up like 'chore run' does, from where it was invoked.
+    pub fn depends(&self) -> &[String] {
+        &self.inner.depends
+    }
+
+    pub fn set_depends<I, S>(&mut self, names: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.inner.depends.clear();
+        for name in names {
+            let name = name.as_ref().trim();
+            if !name.is_empty() && !self.inner.depends.iter().any(|n| n == name) {
+                self.inner.depends.push(name.to_owned());
+            }
+        }
+    }
+
+    // params are the named arguments the task takes, in the order they were declared.
+    pub fn params(&self) -> &[Param] {
+        &self.inner.params
+    }
+
+    pub fn set_params(&mut self, params: Vec<Param>) -> Result<()> {
+        for param in &params {
+            param.check().map_err(TaskError::InvalidParam)?;
+        }
+        self.inner.params = params;
+        Ok(())
+    }
+
+    // set_param sets a field of a parameter given as 'NAME.FIELD', or its type as 'NAME'.
+    // The parameter is declared if it doesn't exist yet.
+    fn set_param(&mut self, spec: &str, value: &str) -> Result<()> {
+        let key = format!("param.{}", spec);
+        let mut parts = spec.splitn(2, '.');
+        let name = parts.next().unwrap_or_default();
+        let field = parts.next().unwrap_or("type");
+
+        let mut params = self.inner.params.clone();
+        let index = match params.iter().position(|p| p.name == name) {
+            Some(index) => index,
+            None => {
+                params.push(Param::new(name));
+                params.len() - 1
+            }
+        };
+
+        {
+            let param = &mut params[index];
+            match field {
+                "type" => {
+                    param.kind = Kind::from_name(value.trim()).ok_or_else(|| {
+                        TaskError::InvalidParam(format!("'{}' is not a type", value))
+                    })?
+                }
+                "default" => param.default = Some(value.to_owned()),
+                "required" => param.required = value == "true" || value == "yes",
+                "choices" => {
+                    param.choices = value
+                        .split(',')
+                        .map(|c| c.trim().to_owned())
+                        .filter(|c| !c.is_empty())
+                        .collect()
+                }
+                "help" => param.help = value.trim().to_owned(),
+                _ => return Err(TaskError::UnknownOption(key.clone())),
+            }
+            param.check().map_err(TaskError::InvalidParam)?;
+        }
+        self.inner.params = params;
+        Ok(())
+    }
+
+    fn unset_param(&mut self, spec: &str) -> Result<()> {
+        let mut parts = spec.splitn(2, '.');
+        let name = parts.next().unwrap_or_default();
+        let param = match self.inner.params.iter_mut().find(|p| p.name == name) {
+            Some(param) => param,
+            None => return Err(TaskError::UnknownOption(format!("param.{}", spec))),
+        };
+
+        match parts.next() {
+            None => {
+                self.inner.params.retain(|p| p.name != name);
+            }
+            Some("type") => param.kind = Kind::String,
+            Some("default") => param.default = None,
+            Some("required") => param.required = false,
+            Some("choices") => param.choices.clear(),
+            Some("help") => param.help.clear(),
+            Some(_) => return Err(TaskError::UnknownOption(format!("param.{}", spec))),
+        }
+        Ok(())
+    }
+
+    // dir is the directory which owns the task.
+    pub fn dir(&self) -> &Path {
+        Path::new(&self.path)
+            .parent()
+            .unwrap_or_else(|| Path::new("/"))
+    }
+
+    // work_dir resolves where the task runs when it is invoked in invoke_dir.
+    pub fn work_dir(&self, invoke_dir: &Path) -> PathBuf {
+        match self.inner.cwd.as_str() {
+            "" | "invoke" => invoke_dir.to_path_buf(),
+            "task" => self.dir().to_path_buf(),
+            path => PathBuf::from(path),
+        }
+    }
+
+    // timeout is how long the task may run before it is stopped.
+    pub fn timeout(&self) -> Option<Duration> {
+        match self.inner.timeout {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    // watch are the globs of the files under the directory of the task which it is rerun on.
+    pub fn watch(&self) -> &[String] {
+        &self.inner.watch
+    }
+
+    // schedule is when 'chore daemon' runs the task, if ever.
+    pub fn schedule(&self) -> Option<Schedule> {
+        Schedule::parse(&self.inner.schedule).ok()
+    }
+
     pub fn copy_from(&mut self, task: &Task) {
         self.inner = task.inner.clone();
     }
 }
 
+// OPTIONS are the options of a task which can be changed after it is created.
+// Variables of env are set one by one as env.NAME, and parameters as param.NAME.FIELD.
+pub const OPTIONS: &[&str] = &[
+    "inherit",
+    "extension",
+    "interpreter",
+    "description",
+    "tags",
+    "env",
+    "cwd",
+    "timeout",
+    "watch",
+    "schedule",
+    "depends",
+    "params",
+];
+
+impl Task {
+    // option prints the value of the option like it is given to set_option.
+    pub fn option(&self, key: &str) -> Result<String> {
+        Ok(match key {
+            "inherit" => self.inherit().to_string(),
+            "extension" => self.extension().to_owned(),
+            "interpreter" => self.interpreter().to_owned(),
+            "description" => self.description().to_owned(),
+            "tags" => self.tags().join(","),
+            "env" => self
+                .env()
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<String>>()
+                .join(" "),
+            "cwd" => self.inner.cwd.clone(),
+            "timeout" => match self.timeout() {
+                Some(timeout) => crate::time::format_seconds(timeout.as_secs()),
+                None => String::new(),
+            },
+            "watch" => self.watch().join(","),
+            "schedule" => self.inner.schedule.clone(),
+            "depends" => self.depends().join(","),
+            "params" => self
+                .params()
+                .iter()
+                .map(|p| p.summary())
+                .collect::<Vec<String>>()
+                .join(" "),
+            _ => return Err(TaskError::UnknownOption(key.to_owned())),
+        })
+    }
+
+    pub fn set_option(&mut self, key: &str, value: &str) -> Result<()> {
+        let invalid = || TaskError::InvalidOption(key.to_owned(), value.to_owned());
+        match key {
+            "inherit" => match value {
+                "true" | "yes" | "on" | "1" => self.set_inherit(true),
+                "false" | "no" | "off" | "0" => self.set_inherit(false),
+                _ => return Err(invalid()),
+            },
+            "extension" => {
+                let ext = value.trim().trim_start_matches('.');
+                if ext.is_empty() || ext.contains('/') {
+                    return Err(invalid());
+                }
+                self.set_extension(ext);
+            }
+            "interpreter" => self.set_interpreter(value),
+            "description" => self.set_description(value),
+            "tags" => self.set_tags(value.split(',')),
+            _ if key.starts_with("env.") => {
+                let name = &key["env.".len()..];
+                match crate::environment::parse_pair(&format!("{}={}", name, value)) {
+                    Some((name, value)) => self.set_env(&name, &value),
+                    None => return Err(invalid()),
+                }
+            }
+            "depends" => {
+                let names: Vec<&str> = value
+                    .split(',')
+                    .map(|n| n.trim())
+                    .filter(|n| !n.is_empty())
+                    .collect();
+                if names
+                    .iter()
+                    .any(|n| *n == "." || *n == ".." || n.contains('/'))
+                {
+                    return Err(invalid());
+                }
+                self.set_depends(names);
+            }
+            "env" | "params" => return Err(invalid()),
+            _ if key.starts_with("param.") => self.set_param(&key["param.".len()..], value)?,
+            "cwd" => {
+                let cwd = value.trim();
+                match cwd {
+                    "" | "invoke" | "task" => (),
+                    _ if Path::new(cwd).is_absolute() => (),
+                    _ => return Err(invalid()),
+                }
+                self.inner.cwd = cwd.to_owned();
+            }
+            "schedule" => {
+                let expression = value.trim();
+                if !expression.is_empty() && Schedule::parse(expression).is_err() {
+                    return Err(invalid());
+                }
+                self.inner.schedule = expression.to_owned();
+            }
+            "watch" => {
+                let globs: Vec<String> = value
+                    .split(',')
+                    .map(|g| g.trim().to_owned())
+                    .filter(|g| !g.is_empty())
+                    .collect();
+                if globs.iter().any(|g| crate::watch::check(g).is_err()) {
+                    return Err(invalid());
+                }
+                self.inner.watch = globs;
+            }
+            "timeout" => {
+                self.inner.timeout = match value.trim() {
+                    "" => 0,
+                    value => crate::time::parse_duration(value).ok_or_else(invalid)?,
+                };
+            }
+            _ => return Err(TaskError::UnknownOption(key.to_owned())),
+        }
+        Ok(())
+    }
+
+    // unset_option puts the option back to the default of a new task.
+    pub fn unset_option(&mut self, key: &str) -> Result<()> {
+        if key == "env" {
+            self.inner.env.clear();
+            return Ok(());
+        }
+        if let Some(name) = key.strip_prefix("env.") {
+            self.inner.env.retain(|(k, _)| k != name);
+            return Ok(());
+        }
+        if key == "params" {
+            self.inner.params.clear();
+            return Ok(());
+        }
+        if let Some(name) = key.strip_prefix("param.") {
+            return self.unset_param(name);
+        }
+
+        let value = match key {
+            "inherit" => "false",
+            "extension" => "sh",
+            _ => "",
+        };
+        self.set_option(key, value)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Revision {
+    number: u64,
+    timestamp: u64,
+    author: String,
+    hash: String,
+    task: Inner,
+}
+
+impl Revision {
+    fn new(number: u64, inner: &Inner) -> Revision {
+        let author = env::var("USER")
+            .or_else(|_| env::var("LOGNAME"))
+            .unwrap_or_else(|_| "unknown".to_owned());
+
+        Revision {
+            number,
+            timestamp: crate::time::now(),
+            author,
+            hash: content_hash(&inner.content),
+            task: inner.clone(),
+        }
+    }
+
+    fn key(abs_path: &str, number: u64) -> String {
+        format!("rev.{}/{:010}", abs_path, number)
+    }
+
+    // same_as compares the content and options, but not the timestamps.
+    fn same_as(&self, inner: &Inner) -> bool {
+        self.hash == content_hash(&inner.content)
+            && serde_json::to_value(self.task.untimed()).ok()
+                == serde_json::to_value(inner.untimed()).ok()
+    }
+
+    pub fn number(&self) -> u64 {
+        self.number
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    pub fn content(&self) -> &[u8] {
+        &self.task.content
+    }
+
+    pub fn inherit(&self) -> bool {
+        self.task.inherit
+    }
+
+    pub fn extension(&self) -> &str {
+        &self.task.extension
+    }
+
+    // restore puts the content and options of this revision back to the task.
+    // When the task was created stays as it is.
+    pub fn restore(&self, task: &mut Task) {
+        let created_at = task.inner.created_at;
+        task.inner = self.task.clone();
+        task.inner.created_at = created_at;
+    }
+}
+
+// last_run_key is where the time the task was last run is kept.
+fn last_run_key(abs_path: &str) -> String {
+    format!("last_run.{}", abs_path)
+}
+
+// Runs are kept in a key space of their own, ordered by when they were recorded.
+// The slash keeps them in a directory of their own in the fs backend.
+const HISTORY_PREFIX: &str = "hist./";
+
+// RunRecord is a run of a task in the history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunRecord {
+    // The path of the task.
+    pub task: String,
+    // Where chore was invoked.
+    pub cwd: String,
+    pub args: Vec<String>,
+    pub started_at: u64,
+    pub duration_ms: u64,
+    pub exit_code: i32,
+    // The last lines of the output, when it went through chore.
+    #[serde(default)]
+    pub output: Vec<String>,
+    // The number of the log, when it was run with --log.
+    #[serde(default)]
+    pub log: Option<u64>,
+}
+
+impl RunRecord {
+    fn key(&self) -> String {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() * 1_000_000_000 + u64::from(d.subsec_nanos()))
+            .unwrap_or(0);
+        format!("{}{:020}-{}", HISTORY_PREFIX, nanos, process::id())
+    }
+}
+
+// Alias is another name of a task. Like a task, it belongs to a directory and is visible
+// from the subdirectories only when it is inherited. The target is looked up from there.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Alias {
+    path: String,
+    target: String,
+    inherit: bool,
+}
+
+impl Alias {
+    pub fn new<P: AsRef<Path>>(path: P, target: &str, inherit: bool) -> Alias {
+        Alias {
+            path: path.as_ref().to_string_lossy().into_owned(),
+            target: target.to_owned(),
+            inherit,
+        }
+    }
+
+    fn key(path: &str) -> String {
+        format!("alias.{}", path)
+    }
+
+    pub fn name(&self) -> &str {
+        Path::new(&self.path)
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or(&self.path)
+    }
+
+    pub fn dir(&self) -> &Path {
+        Path::new(&self.path)
+            .parent()
+            .unwrap_or_else(|| Path::new("/"))
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub fn inherit(&self) -> bool {
+        self.inherit
+    }
+}
+
+// content_hash is a 64-bit FNV-1a hash, which is stable across builds unlike DefaultHasher.
+fn content_hash(content: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in content {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 struct Inner {
     inherit: bool,
     #[serde(default = "String::default")]
     extension: String,
+    #[serde(default = "String::default")]
+    interpreter: String,
+    #[serde(default = "String::default")]
+    description: String,
+    #[serde(default = "Vec::default")]
+    tags: Vec<String>,
+    #[serde(default = "Vec::default")]
+    env: Vec<(String, String)>,
+    #[serde(default = "Vec::default")]
+    depends: Vec<String>,
+    #[serde(default = "Vec::default")]
+    params: Vec<Param>,
+    // Where the task runs: invoke (the default), task or an absolute path.
+    #[serde(default = "String::default")]
+    cwd: String,
+    // Seconds the task may run before it is stopped, zero for no limit.
+    #[serde(default)]
+    timeout: u64,
+    // Globs of the files 'chore run --watch' reruns the task on.
+    #[serde(default = "Vec::default")]
+    watch: Vec<String>,
+    // A cron expression of when 'chore daemon' runs the task.
+    #[serde(default = "String::default")]
+    schedule: String,
+    // Unix timestamps, zero for unknown or never.
+    #[serde(default)]
+    created_at: u64,
+    #[serde(default)]
+    updated_at: u64,
     content: Vec<u8>,
 }
 
-impl Default for Inner {
-    fn default() -> Self {
+impl Inner {
+    fn untimed(&self) -> Inner {
         Inner {
-            content: Vec::new(),
-            extension: String::new(),
-            inherit: false,
+            created_at: 0,
+            updated_at: 0,
+            ..self.clone()
         }
     }
 }
@@ -238,34 +1066,49 @@ impl Default for Inner {
 pub enum TaskError {
     InvalidPath,
     NotFound(String),
+    RevisionNotFound(u64),
+    UnknownOption(String),
+    InvalidOption(String, String),
+    InvalidParam(String),
     BrokenData,
-    DBOperationFailed(rocksdb::Error),
+    DBOperationFailed(StoreError),
 }
 
 impl fmt::Display for TaskError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use std::error::Error;
         match *self {
-            // TaskError::InvalidPath(ref s) => write!(f, "{}: {}", s, self.description()),
-            TaskError::NotFound(ref s) => write!(f, "{}: {}", s, self.description()),
-            _ => write!(f, "{}", self.description()),
+            // TaskError::InvalidPath(ref s) => write!(f, "{}: {}", s, self.summary()),
+            TaskError::NotFound(ref s) => write!(f, "{}: {}", s, self.summary()),
+            TaskError::RevisionNotFound(n) => write!(f, "{}: {}", self.summary(), n),
+            TaskError::UnknownOption(ref key) => write!(f, "{}: {}", self.summary(), key),
+            TaskError::InvalidOption(ref key, ref value) => {
+                write!(f, "{}: {}={}", self.summary(), key, value)
+            }
+            TaskError::InvalidParam(ref message) => write!(f, "{}", message),
+            _ => write!(f, "{}", self.summary()),
         }
     }
 }
 
-impl error::Error for TaskError {
-    fn description(&self) -> &str {
+impl error::Error for TaskError {}
+
+impl TaskError {
+    fn summary(&self) -> &str {
         match *self {
             TaskError::InvalidPath => "Invalid path",
             TaskError::NotFound(_) => "No available task",
+            TaskError::RevisionNotFound(_) => "No such revision",
+            TaskError::UnknownOption(_) => "No such option",
+            TaskError::InvalidOption(_, _) => "Invalid value for the option",
+            TaskError::InvalidParam(_) => "Invalid parameter",
             TaskError::BrokenData => "Broken data",
-            TaskError::DBOperationFailed(ref e) => e.description(),
+            TaskError::DBOperationFailed(ref e) => e.message(),
         }
     }
 }
 
-impl From<rocksdb::Error> for TaskError {
-    fn from(err: rocksdb::Error) -> TaskError {
+impl From<StoreError> for TaskError {
+    fn from(err: StoreError) -> TaskError {
         TaskError::DBOperationFailed(err)
     }
 }
@@ -275,3 +1118,136 @@ impl From<serde_json::Error> for TaskError {
         TaskError::BrokenData
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{RunRecord, Task, TaskSystem};
+    use crate::store::MemoryStore;
+    use std::path::Path;
+
+    fn task(path: &str, content: &str) -> Task {
+        let mut task = Task::at(path);
+        task.set_content(content.as_bytes().to_vec());
+        task
+    }
+
+    fn numbers(ts: &TaskSystem, path: &str) -> Vec<u64> {
+        ts.revisions(path)
+            .unwrap()
+            .iter()
+            .map(|r| r.number())
+            .collect()
+    }
+
+    #[test]
+    fn options_are_checked_and_unset_to_their_defaults() {
+        let mut task = Task::at("/a/build");
+        task.set_option("extension", ".py").unwrap();
+        task.set_option("inherit", "yes").unwrap();
+        assert_eq!(task.option("extension").unwrap(), "py");
+        assert_eq!(task.option("inherit").unwrap(), "true");
+        assert!(task.set_option("inherit", "maybe").is_err());
+        assert!(task.set_option("colour", "red").is_err());
+
+        task.unset_option("extension").unwrap();
+        task.unset_option("inherit").unwrap();
+        assert_eq!(task.option("extension").unwrap(), "sh");
+        assert_eq!(task.option("inherit").unwrap(), "false");
+    }
+
+    #[test]
+    fn work_dir_follows_the_cwd_option() {
+        let invoke = Path::new("/home/me/src");
+        let mut task = Task::at("/home/me/build");
+        assert_eq!(task.work_dir(invoke), invoke);
+        task.set_option("cwd", "task").unwrap();
+        assert_eq!(task.work_dir(invoke), Path::new("/home/me"));
+        task.set_option("cwd", "/tmp").unwrap();
+        assert_eq!(task.work_dir(invoke), Path::new("/tmp"));
+        assert!(task.set_option("cwd", "relative/dir").is_err());
+    }
+
+    #[test]
+    fn history_keeps_the_latest_runs() {
+        let mut ts = TaskSystem::new(Box::new(MemoryStore::new()));
+        ts.keep_history(2);
+        for started_at in 1..4 {
+            let record = RunRecord {
+                task: "/a/build".to_owned(),
+                cwd: "/a".to_owned(),
+                args: Vec::new(),
+                started_at,
+                duration_ms: 0,
+                exit_code: 0,
+                output: Vec::new(),
+                log: None,
+            };
+            ts.record_run(&record).unwrap();
+        }
+
+        let history = ts.history().unwrap();
+        let started: Vec<u64> = history.iter().map(|r| r.started_at).collect();
+        assert_eq!(started, vec![2, 3]);
+    }
+
+    #[test]
+    fn saves_in_one_batch_get_their_own_revisions() {
+        let ts = TaskSystem::new(Box::new(MemoryStore::new()));
+        let mut batch = ts.batch();
+        batch.save(&task("/a/build", "echo 1")).unwrap();
+        batch.save(&task("/a/build", "echo 2")).unwrap();
+        batch.save(&task("/a/build", "echo 2")).unwrap();
+        batch.commit().unwrap();
+
+        assert_eq!(numbers(&ts, "/a/build"), vec![1, 2]);
+        assert_eq!(ts.open("/a/build").unwrap().content(), b"echo 2");
+    }
+
+    #[test]
+    fn old_revisions_over_the_limit_are_dropped() {
+        let mut ts = TaskSystem::new(Box::new(MemoryStore::new()));
+        ts.keep_revisions(2);
+        let mut batch = ts.batch();
+        for n in 1..5 {
+            batch
+                .save(&task("/a/build", &format!("echo {}", n)))
+                .unwrap();
+        }
+        batch.commit().unwrap();
+
+        assert_eq!(numbers(&ts, "/a/build"), vec![3, 4]);
+    }
+
+    #[test]
+    fn moved_task_keeps_its_revisions() {
+        let ts = TaskSystem::new(Box::new(MemoryStore::new()));
+        ts.save(&task("/a/build", "echo 1")).unwrap();
+        ts.save(&task("/a/build", "echo 2")).unwrap();
+        ts.save(&task("/b/build", "echo 3")).unwrap();
+
+        let mut batch = ts.batch();
+        batch.carry_revisions("/a/build", "/b/build", true).unwrap();
+        batch.save(&task("/b/build", "echo 2")).unwrap();
+        batch.remove("/a/build").unwrap();
+        batch.commit().unwrap();
+
+        assert_eq!(numbers(&ts, "/a/build"), Vec::<u64>::new());
+        assert_eq!(numbers(&ts, "/b/build"), vec![1, 2, 3]);
+        assert_eq!(ts.revision("/b/build", 2).unwrap().content(), b"echo 1");
+    }
+
+    #[test]
+    fn last_run_leaves_the_task_as_it_is() {
+        let ts = TaskSystem::new(Box::new(MemoryStore::new()));
+        ts.save(&task("/a/build", "echo 1")).unwrap();
+        let build = ts.open("/a/build").unwrap();
+        ts.set_last_run(&build, 42).unwrap();
+
+        let build = ts.open("/a/build").unwrap();
+        assert_eq!(ts.last_run_at(&build).unwrap(), 42);
+        assert_eq!(numbers(&ts, "/a/build"), vec![1]);
+
+        ts.remove("/a/build").unwrap();
+        assert_eq!(ts.last_run_at(&build).unwrap(), 0);
+    }
+}
@@ -1,13 +1,87 @@
 use std::env;
-use std::fs::{self, File};
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use libc;
+use rand::{thread_rng, Rng};
 
 use crate::error::{Error, Result};
 
+// random_name makes a hidden file name with the extension, like '.chorea1B2c3D4.sh'.
+pub fn random_name(ext: &str) -> String {
+    let length = 8;
+    let charset = b"abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+    let mut rng = thread_rng();
+    let mut file_name = ".chore".to_string();
+
+    for _ in 0..length {
+        file_name.push(*rng.choose(charset).unwrap() as char);
+    }
+
+    if !ext.is_empty() {
+        if !ext.starts_with('.') {
+            file_name.push('.');
+        }
+        file_name.push_str(ext);
+    }
+    file_name
+}
+
+// Paths of the temporary files which exist, so they can be removed when chore is killed by
+// a signal and their Drop doesn't run. A slot is null when it is free.
+static PATHS: [AtomicPtr<libc::c_char>; 16] = [const { AtomicPtr::new(ptr::null_mut()) }; 16];
+
+// remove_all removes the temporary files. It is called from the signal handler,
+// so it only uses async-signal-safe calls.
+pub fn remove_all() {
+    for path in PATHS.iter() {
+        let path = path.load(Ordering::SeqCst);
+        if !path.is_null() {
+            unsafe {
+                libc::unlink(path);
+            }
+        }
+    }
+}
+
+// Tracked is the path of a temporary file in PATHS.
+struct Tracked {
+    slot: Option<usize>,
+    // The slot points into the buffer of it, so it lives as long as the slot is taken.
+    _path: CString,
+}
+
+impl Tracked {
+    fn new(path: &Path) -> Tracked {
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap_or_default();
+        let raw = path.as_ptr() as *mut libc::c_char;
+        let slot = PATHS.iter().position(|p| {
+            p.compare_exchange(ptr::null_mut(), raw, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        });
+        Tracked { slot, _path: path }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            PATHS[slot].store(ptr::null_mut(), Ordering::SeqCst);
+        }
+    }
+}
+
 pub struct TempFile {
     inner: Option<File>,
     path: PathBuf,
+    _tracked: Tracked,
 }
 
 impl TempFile {
@@ -16,7 +90,30 @@ impl TempFile {
         path.push(file_path);
         let inner = Some(File::create(path.clone())?);
 
-        Ok(TempFile { inner, path })
+        Ok(TempFile {
+            inner,
+            _tracked: Tracked::new(&path),
+            path,
+        })
+    }
+
+    // create_executable creates a new file in dir which only the owner can read, write and execute.
+    // It fails rather than opening a file which already exists.
+    pub fn create_executable<P: AsRef<Path>>(dir: P, file_name: &str) -> Result<TempFile> {
+        let path = dir.as_ref().join(file_name);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o700)
+            .open(&path)?;
+        // The mode above is masked by umask.
+        file.set_permissions(fs::Permissions::from_mode(0o700))?;
+
+        Ok(TempFile {
+            inner: Some(file),
+            _tracked: Tracked::new(&path),
+            path,
+        })
     }
 
     pub fn path(&self) -> &Path {
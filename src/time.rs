@@ -0,0 +1,229 @@
+use std::mem;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libc;
+
+// now returns the current time in seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// format prints a unix timestamp as 'YYYY-MM-DD hh:mm:ss' in UTC.
+pub fn format(secs: u64) -> String {
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let rem = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+// Units of a duration, in seconds.
+const UNITS: &[(char, u64)] = &[
+    ('s', 1),
+    ('m', 60),
+    ('h', 3600),
+    ('d', 86_400),
+    ('w', 604_800),
+];
+
+// parse_since reads a point in the past, either relative to now like '30m', '12h', '7d' and '2w',
+// or a date in UTC like '2018-05-01'.
+pub fn parse_since(s: &str, now: u64) -> Option<u64> {
+    let s = s.trim();
+    if s.ends_with(|c: char| c.is_ascii_alphabetic()) {
+        return parse_amount(s).map(|secs| now.saturating_sub(secs));
+    }
+
+    let parts: Vec<&str> = s.split('-').collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let year: i64 = parts[0].parse().ok()?;
+    let month: u32 = parts[1].parse().ok()?;
+    let day: u32 = parts[2].parse().ok()?;
+    if year > 9999 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // A day past the end of the month, like 04-31, comes back as another date.
+    let days = days_from_civil(year, month, day);
+    if days < 0 || civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+    (days as u64).checked_mul(86_400)
+}
+
+// parse_duration reads a duration in seconds, like '90', '30s', '10m' or '2h'.
+pub fn parse_duration(s: &str) -> Option<u64> {
+    let s = s.trim();
+    match s.parse() {
+        Ok(secs) => Some(secs),
+        Err(_) => parse_amount(s),
+    }
+}
+
+// format_seconds prints a duration the way parse_duration reads it, in the largest unit which fits.
+pub fn format_seconds(secs: u64) -> String {
+    match UNITS
+        .iter()
+        .rev()
+        .find(|&&(_, unit)| secs > 0 && secs.is_multiple_of(unit))
+    {
+        Some(&(name, unit)) => format!("{}{}", secs / unit, name),
+        None => format!("{}s", secs),
+    }
+}
+
+fn parse_amount(s: &str) -> Option<u64> {
+    let unit = s.chars().last()?;
+    let &(_, secs) = UNITS.iter().find(|&&(name, _)| name == unit)?;
+    let n: u64 = s[..s.len() - 1].parse().ok()?;
+    n.checked_mul(secs)
+}
+
+// days_from_civil is the inverse of civil_from_days.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = i64::from(if month > 2 { month - 3 } else { month + 9 });
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// Local is a point in time in the local time zone, to the minute.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Local {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+}
+
+impl Local {
+    // at converts a unix timestamp into the local time.
+    pub fn at(secs: u64) -> Option<Local> {
+        let t = secs as libc::time_t;
+        let mut tm: libc::tm = unsafe { mem::zeroed() };
+        if unsafe { libc::localtime_r(&t, &mut tm) }.is_null() {
+            return None;
+        }
+        Some(Local {
+            year: i64::from(tm.tm_year) + 1900,
+            month: (tm.tm_mon + 1) as u32,
+            day: tm.tm_mday as u32,
+            hour: tm.tm_hour as u32,
+            minute: tm.tm_min as u32,
+        })
+    }
+
+    // timestamp converts the local time into a unix timestamp. It is None for a time
+    // which is skipped when the clock is put forward.
+    pub fn timestamp(&self) -> Option<u64> {
+        let mut tm: libc::tm = unsafe { mem::zeroed() };
+        tm.tm_year = (self.year - 1900) as libc::c_int;
+        tm.tm_mon = self.month as libc::c_int - 1;
+        tm.tm_mday = self.day as libc::c_int;
+        tm.tm_hour = self.hour as libc::c_int;
+        tm.tm_min = self.minute as libc::c_int;
+        tm.tm_isdst = -1;
+        let t = unsafe { libc::mktime(&mut tm) };
+        if t < 0 || tm.tm_hour as u32 != self.hour || tm.tm_min as u32 != self.minute {
+            return None;
+        }
+        Some(t as u64)
+    }
+}
+
+// format_local prints a unix timestamp as 'YYYY-MM-DD hh:mm' in the local time zone.
+pub fn format_local(secs: u64) -> String {
+    match Local::at(secs) {
+        Some(t) => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            t.year, t.month, t.day, t.hour, t.minute
+        ),
+        None => "-".to_owned(),
+    }
+}
+
+// format_duration prints a duration in milliseconds shortly, like '850ms', '4.2s' or '3m05s'.
+pub fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    if ms < 1000 {
+        format!("{}ms", ms)
+    } else if secs < 60 {
+        format!("{}.{}s", secs, ms % 1000 / 100)
+    } else if secs < 3600 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+// civil_from_days converts days since 1970-01-01 into a (year, month, day) triple.
+// See http://howardhinnant.github.io/date_algorithms.html
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_seconds, parse_duration, parse_since};
+
+    #[test]
+    fn since_is_relative_or_a_date() {
+        assert_eq!(parse_since("30m", 10_000), Some(10_000 - 1800));
+        assert_eq!(parse_since("2w", 10_000), Some(0));
+        assert_eq!(parse_since("1970-01-02", 0), Some(86_400));
+        assert_eq!(parse_since("1969-12-31", 0), None);
+        assert_eq!(parse_since("2018-13-01", 0), None);
+        assert_eq!(parse_since("7x", 0), None);
+    }
+
+    #[test]
+    fn days_past_the_end_of_the_month_are_rejected() {
+        assert_eq!(parse_since("2020-02-29", 0), Some(1_582_934_400));
+        assert_eq!(parse_since("2019-02-29", 0), None);
+        assert_eq!(parse_since("2000-02-29", 0), Some(951_782_400));
+        assert_eq!(parse_since("2100-02-29", 0), None);
+        assert_eq!(parse_since("2018-04-31", 0), None);
+        assert_eq!(parse_since("2018-04-30", 0), Some(1_525_046_400));
+    }
+
+    #[test]
+    fn huge_amounts_are_rejected() {
+        assert_eq!(parse_since("99999999999999999w", 10_000), None);
+        assert_eq!(parse_since("9223372036854775807-01-01", 0), None);
+        assert_eq!(parse_duration("99999999999999999h"), None);
+    }
+
+    #[test]
+    fn durations_are_read_and_printed_back() {
+        assert_eq!(parse_duration("90"), Some(90));
+        assert_eq!(parse_duration(" 10m "), Some(600));
+        assert_eq!(parse_duration("2h"), Some(7200));
+        assert_eq!(parse_duration("ten"), None);
+        assert_eq!(format_seconds(600), "10m");
+        assert_eq!(format_seconds(90), "90s");
+        assert_eq!(format_seconds(0), "0s");
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+#[cfg(not(target_os = "linux"))]
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::error::{Error, Result, EXIT_USAGE};
+
+// How long the files have to stay quiet before a burst of changes is reported at once.
+#[cfg(target_os = "linux")]
+const DEBOUNCE_MS: i32 = 200;
+
+// Directories which builds usually write to. They are left out when every file is watched,
+// or a task writing there would be run again and again by its own output.
+const OUTPUT_DIRS: &[&str] = &[
+    "target",
+    "build",
+    "dist",
+    "out",
+    "node_modules",
+    "__pycache__",
+];
+
+// Watcher reports changes of the files under root which match one of the globs.
+// Without globs, it reports every file but the ones in output directories.
+pub struct Watcher {
+    fd: i32,
+    root: PathBuf,
+    globs: Vec<String>,
+    dirs: HashMap<i32, PathBuf>,
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::fs;
+    use std::io;
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::ptr;
+
+    use libc;
+
+    use super::{watched, Watcher, DEBOUNCE_MS, OUTPUT_DIRS};
+    use crate::error::Result;
+
+    const EVENTS: u32 = libc::IN_CLOSE_WRITE
+        | libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO;
+
+    impl Watcher {
+        pub fn new(root: &Path, globs: &[String]) -> Result<Watcher> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            let mut watcher = Watcher {
+                fd,
+                root: root.to_path_buf(),
+                globs: globs.to_vec(),
+                dirs: HashMap::new(),
+            };
+            watcher.add_dir(root)?;
+            Ok(watcher)
+        }
+
+        // wait blocks until matching files change, and returns them once the changes settle.
+        pub fn wait(&mut self) -> Result<Vec<PathBuf>> {
+            let mut changed: Vec<PathBuf> = Vec::new();
+            loop {
+                let timeout = if changed.is_empty() { -1 } else { DEBOUNCE_MS };
+                if !self.poll(timeout)? {
+                    return Ok(changed);
+                }
+                for path in self.read()? {
+                    if !changed.contains(&path) && self.matches(&path) {
+                        changed.push(path);
+                    }
+                }
+            }
+        }
+
+        // add_dir watches dir and its subdirectories. Hidden directories are not watched,
+        // nor output directories when every file is.
+        // Only a failure on root is an error; a subdirectory may be gone or unreadable.
+        fn add_dir(&mut self, dir: &Path) -> Result<()> {
+            let path = CString::new(dir.as_os_str().as_bytes()).unwrap_or_default();
+            let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), EVENTS) };
+            if wd < 0 {
+                if dir == self.root {
+                    return Err(io::Error::last_os_error().into());
+                }
+                return Ok(());
+            }
+            self.dirs.insert(wd, dir.to_path_buf());
+
+            if let Ok(entries) = fs::read_dir(dir) {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+                    if is_dir && self.descends(&entry.file_name().to_string_lossy()) {
+                        let _ = self.add_dir(&entry.path());
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        // poll waits for events up to timeout milliseconds, or forever when it is negative.
+        fn poll(&self, timeout: i32) -> Result<bool> {
+            let mut fds = libc::pollfd {
+                fd: self.fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            loop {
+                match unsafe { libc::poll(&mut fds, 1, timeout) } {
+                    n if n >= 0 => return Ok(n > 0),
+                    _ => {
+                        let e = io::Error::last_os_error();
+                        if e.kind() != io::ErrorKind::Interrupted {
+                            return Err(e.into());
+                        }
+                    }
+                }
+            }
+        }
+
+        // read returns the files of the pending events. A new directory is watched as well.
+        // When the queue overflowed, root is returned to tell that anything may have changed.
+        fn read(&mut self) -> Result<Vec<PathBuf>> {
+            let mut paths = Vec::new();
+            let mut buf = vec![0u8; 64 * 1024];
+            loop {
+                let n = unsafe {
+                    libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len())
+                };
+                if n < 0 {
+                    let e = io::Error::last_os_error();
+                    match e.kind() {
+                        io::ErrorKind::WouldBlock => return Ok(paths),
+                        io::ErrorKind::Interrupted => continue,
+                        _ => return Err(e.into()),
+                    }
+                }
+
+                let header = mem::size_of::<libc::inotify_event>();
+                let mut offset = 0;
+                while offset + header <= n as usize {
+                    let event: libc::inotify_event = unsafe {
+                        ptr::read_unaligned(buf[offset..].as_ptr() as *const libc::inotify_event)
+                    };
+                    let name = &buf[offset + header..offset + header + event.len as usize];
+                    let name = name.split(|&b| b == 0).next().unwrap_or(&[]);
+                    offset += header + event.len as usize;
+
+                    if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                        paths.push(self.root.clone());
+                        continue;
+                    }
+                    if event.mask & libc::IN_IGNORED != 0 {
+                        self.dirs.remove(&event.wd);
+                        continue;
+                    }
+                    let path = match self.dirs.get(&event.wd) {
+                        Some(dir) => dir.join(OsStr::from_bytes(name)),
+                        None => continue,
+                    };
+                    if event.mask & libc::IN_ISDIR != 0 {
+                        if event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0
+                            && path
+                                .file_name()
+                                .map(|n| self.descends(&n.to_string_lossy()))
+                                .unwrap_or(false)
+                        {
+                            let _ = self.add_dir(&path);
+                        }
+                        continue;
+                    }
+                    paths.push(path);
+                }
+            }
+        }
+
+        fn matches(&self, path: &Path) -> bool {
+            if path == self.root {
+                return true;
+            }
+            let relative = match path.strip_prefix(&self.root) {
+                Ok(relative) => relative.to_string_lossy().into_owned(),
+                Err(_) => return false,
+            };
+            watched(&self.globs, &relative)
+        }
+
+        // descends tells whether the subdirectory of the name is watched.
+        fn descends(&self, name: &str) -> bool {
+            !(name.starts_with('.') || self.globs.is_empty() && OUTPUT_DIRS.contains(&name))
+        }
+    }
+
+    impl Drop for Watcher {
+        fn drop(&mut self) {
+            unsafe {
+                libc::close(self.fd);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    pub fn new(_: &Path, _: &[String]) -> Result<Watcher> {
+        Err(
+            Error::new("Watching files needs inotify, which is only on Linux")
+                .with_code(EXIT_USAGE),
+        )
+    }
+
+    pub fn wait(&mut self) -> Result<Vec<PathBuf>> {
+        Ok(Vec::new())
+    }
+}
+
+// check tells whether the glob is valid, which is relative and stays under the directory.
+pub fn check(glob: &str) -> Result<()> {
+    if glob.is_empty() || glob.starts_with('/') || glob.split('/').any(|s| s == "..") {
+        return Err(Error::with_suggest(
+            format!("'{}' is invalid glob to watch", glob),
+            "It should be relative to the directory of the task, like src/**/*.rs or *.toml"
+                .to_owned(),
+        )
+        .with_code(EXIT_USAGE));
+    }
+    Ok(())
+}
+
+// watched tells whether a change of the path relative to the root is reported.
+// Without globs, every file is but the ones in output directories.
+pub fn watched(globs: &[String], path: &str) -> bool {
+    if globs.is_empty() {
+        let mut dirs = path.split('/');
+        dirs.next_back();
+        return matches("*", path) && !dirs.any(|dir| OUTPUT_DIRS.contains(&dir));
+    }
+    globs.iter().any(|glob| matches(glob, path))
+}
+
+// matches tells whether the path relative to the root matches the glob.
+// A glob without a slash matches the file name at any depth, like *.rs.
+// Otherwise, * and ? match within a directory and ** matches any number of directories.
+// Like a shell, a wildcard doesn't match the leading dot of a hidden file.
+pub fn matches(glob: &str, path: &str) -> bool {
+    let path: Vec<&str> = path.split('/').collect();
+    if !glob.contains('/') {
+        return match path.last() {
+            Some(name) => match_segment(glob.as_bytes(), name.as_bytes()),
+            None => false,
+        };
+    }
+    let glob: Vec<&str> = glob.split('/').filter(|s| !s.is_empty()).collect();
+    match_segments(&glob, &path)
+}
+
+fn match_segments(glob: &[&str], path: &[&str]) -> bool {
+    match glob.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len())
+            .take_while(|&i| i == 0 || !path[i - 1].starts_with('.'))
+            .any(|i| match_segments(rest, &path[i..])),
+        Some((segment, rest)) => match path.split_first() {
+            Some((name, path)) => {
+                match_segment(segment.as_bytes(), name.as_bytes()) && match_segments(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_segment(glob: &[u8], name: &[u8]) -> bool {
+    if name.starts_with(b".") && !glob.starts_with(b".") {
+        return false;
+    }
+    match_bytes(glob, name)
+}
+
+fn match_bytes(glob: &[u8], name: &[u8]) -> bool {
+    match glob.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| match_bytes(rest, &name[i..])),
+        Some((b'?', rest)) => !name.is_empty() && match_bytes(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && match_bytes(rest, &name[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, matches, watched};
+
+    #[test]
+    fn glob_without_slash_matches_name_at_any_depth() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", "src/store/fs.rs"));
+        assert!(matches("Cargo.toml", "Cargo.toml"));
+        assert!(!matches("*.rs", "src/main.rs.orig"));
+        assert!(matches("?.py", "a/b.py"));
+        assert!(!matches("?.py", "a/bc.py"));
+    }
+
+    #[test]
+    fn glob_with_slash_matches_from_the_root() {
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "src/store/fs.rs"));
+        assert!(!matches("src/*.rs", "lib/src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/store/fs.rs"));
+        assert!(matches("**/test", "a/b/test"));
+        assert!(!matches("src/**/*.rs", "tests/main.rs"));
+    }
+
+    #[test]
+    fn wildcards_skip_hidden_files() {
+        assert!(!matches("*", ".env"));
+        assert!(matches(".env", ".env"));
+        assert!(!matches("**/*.rs", ".git/hooks/a.rs"));
+        assert!(matches(".github/*.yml", ".github/ci.yml"));
+    }
+
+    #[test]
+    fn every_file_but_output_directories_without_globs() {
+        assert!(watched(&[], "src/main.rs"));
+        assert!(watched(&[], "build.rs"));
+        assert!(!watched(&[], "target/debug/chore"));
+        assert!(!watched(&[], "web/node_modules/a/index.js"));
+        assert!(!watched(&[], ".env"));
+        assert!(watched(&["target/*.txt".to_owned()], "target/a.txt"));
+    }
+
+    #[test]
+    fn globs_stay_under_the_directory() {
+        assert!(check("src/**/*.rs").is_ok());
+        assert!(check("").is_err());
+        assert!(check("/etc/*").is_err());
+        assert!(check("../*.rs").is_err());
+    }
+}